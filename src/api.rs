@@ -1,12 +1,30 @@
 use stonkks_core::api::DynApi;
+use stonkks_core::errors::StonkksError;
 use stonkks_core::pointers::*;
 use stonkks_core::predule::*;
+use stonkks_core::request::{RequestParts, DEFAULT_BODY_LIMIT};
 use stonkks_core::response::Response;
 use stonkks_core::routes::UrlInfos;
 use stonkks_core::states::StatesMap;
 
-#[derive(Default)]
-pub struct ApiRoutes(Vec<Box<dyn DynApi>>);
+/// Api route matching an url, or the methods served by the routes matching it.
+type FoundApi<'a, 'url> = Result<(&'a dyn DynApi, RouteUntypedPtr<'url>), Vec<Method>>;
+
+pub struct ApiRoutes {
+    routes: Vec<Box<dyn DynApi>>,
+    body_limit: usize,
+    cors: Option<Cors>,
+}
+
+impl Default for ApiRoutes {
+    fn default() -> Self {
+        ApiRoutes {
+            routes: Vec::new(),
+            body_limit: DEFAULT_BODY_LIMIT,
+            cors: None,
+        }
+    }
+}
 
 impl ApiRoutes {
     pub fn add_route<T: Api>(&mut self, route: T) {
@@ -18,18 +36,43 @@ impl ApiRoutes {
     where
         I: IntoIterator<Item = Box<dyn DynApi>>,
     {
-        self.0.extend(routes);
+        for route in routes {
+            self.add_boxed_route(route);
+        }
     }
 
     pub fn add_boxed_route(&mut self, route: Box<dyn DynApi>) {
-        self.0.push(route);
+        if route.methods().contains(&Method::Options) {
+            panic!("Api routes can't serve OPTIONS, it is used by the CORS preflight requests.");
+        }
+        self.routes.push(route);
+    }
+
+    pub fn set_body_limit(&mut self, limit: usize) {
+        self.body_limit = limit;
+    }
+
+    pub fn set_cors(&mut self, cors: Cors) {
+        self.cors = Some(cors);
+    }
+
+    pub fn cors(&self) -> Option<&Cors> {
+        self.cors.as_ref()
+    }
+
+    /// Body limit of the route matching the url and method, the default limit if none match.
+    pub fn body_limit<'a, 'url>(&self, method: Method, url_infos: UrlInfos<'a, 'url>) -> usize {
+        match self.find_api_for_method(method, url_infos) {
+            Some(Ok((api, _))) => api.body_limit().unwrap_or(self.body_limit),
+            _ => self.body_limit,
+        }
     }
 
     pub fn find_api<'a, 'url>(
         &self,
         url_infos: UrlInfos<'a, 'url>,
     ) -> Option<(&'_ dyn DynApi, RouteUntypedPtr<'url>)> {
-        for api in &self.0 {
+        for api in &self.routes {
             if let Some(route) = api.try_match_route(url_infos) {
                 return Some((&**api, route));
             }
@@ -37,13 +80,72 @@ impl ApiRoutes {
         None
     }
 
+    /// Find the route matching the url and serving the method.
+    /// If routes match the url but none of them serve the method,
+    /// return the methods they serve.
+    pub fn find_api_for_method<'a, 'url>(
+        &self,
+        method: Method,
+        url_infos: UrlInfos<'a, 'url>,
+    ) -> Option<FoundApi<'_, 'url>> {
+        let mut allowed = Vec::new();
+        for api in &self.routes {
+            let methods = Self::served_methods(&**api);
+            if methods.contains(&method) {
+                if let Some(route) = api.try_match_route(url_infos) {
+                    return Some(Ok((&**api, route)));
+                }
+            } else if api.try_match_route(url_infos).is_some() {
+                for method in methods {
+                    if !allowed.contains(&method) {
+                        allowed.push(method);
+                    }
+                }
+            }
+        }
+        (!allowed.is_empty()).then_some(Err(allowed))
+    }
+
+    /// Methods of the route, with HEAD if it serves GET.
+    fn served_methods(api: &dyn DynApi) -> Vec<Method> {
+        let mut methods = api.methods().to_vec();
+        let get = methods.iter().position(|method| *method == Method::Get);
+        if let (Some(get), false) = (get, methods.contains(&Method::Head)) {
+            methods.insert(get + 1, Method::Head);
+        }
+        methods
+    }
+
     pub async fn find_and_respond<'a, 'url>(
         &self,
+        method: Method,
         url_infos: UrlInfos<'a, 'url>,
         states: &StatesMap,
-    ) -> Option<Result<Response, String>> {
-        let (api, route) = self.find_api(url_infos)?;
-        let response = unsafe { api.respond(route, states).await };
+        headers: &Headers,
+        body: &RequestBody,
+    ) -> Option<Result<Response, StonkksError>> {
+        let (api, route) = match self.find_api_for_method(method, url_infos)? {
+            Ok(found) => found,
+            Err(allowed) => return Some(Err(StonkksError::MethodNotAllowed(allowed))),
+        };
+        let parts = RequestParts {
+            states,
+            headers,
+            query: url_infos.query(),
+        };
+        // HEAD requests are answered as GET ones, without the body.
+        let is_head = method == Method::Head && !api.methods().contains(&Method::Head);
+        let method = if is_head { Method::Get } else { method };
+        let response = unsafe {
+            api.respond(method, route, parts, body, self.body_limit)
+                .await
+        };
+        let response = response.map(|mut response| {
+            if is_head {
+                response.content = ResponseBody::Full(Vec::new());
+            }
+            response
+        });
         Some(response)
     }
 }
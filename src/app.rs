@@ -1,14 +1,17 @@
 use std::any::Any;
 use std::collections::hash_map::DefaultHasher;
-use std::fmt::Debug;
 use std::path::PathBuf;
 
 use crate::api::ApiRoutes;
+use crate::cache::PageCache;
 use crate::client::Client;
+use crate::islands::Islands;
+use crate::middleware::{Middleware, Middlewares, RouteGroup};
 use crate::pages::StaticPages;
-use crate::utils::StaticPageAndRoute;
+use crate::utils::{PageAndProps, StaticPageAndRoute};
+use crate::websocket::WebSocketRoutes;
 
-use super::default::{AppLayout, NotFound};
+use super::default::{AppLayout, ErrorPageComponent, NotFound};
 use super::pages::DynPages;
 use super::prelude::*;
 use async_fs as fs;
@@ -17,13 +20,18 @@ use futures::stream::FuturesUnordered;
 use futures::{AsyncReadExt, AsyncWriteExt, TryStreamExt};
 use std::hash::{Hash, Hasher};
 use stonkks_core::api::DynApi;
+use stonkks_core::head::escape_json_for_script;
+use stonkks_core::islands::IslandsRender;
 use stonkks_core::layout::DynLayout;
 use stonkks_core::pages::{DynComponent, DynPageDyn, DynRenderResult, DynStaticPage, StaticPage};
+use stonkks_core::server_only::ServerOnlyContext;
 use stonkks_core::states::StatesMap;
 use sycamore::prelude::*;
+use sycamore::utils::hydrate::with_no_hydration_context;
 
-pub const SERIALIZED_PROPS_KEY: &str = "__STONKKS_SERIALIZED_PROPS__";
-pub const STONKKS_WINDOW_OBJECT_KEY: &str = "__STONKKS_OBJECT__";
+pub const PROPS_ELEMENT_ID: &str = "__STONKKS_PROPS__";
+pub const PROPS_CODEC_ATTRIBUTE: &str = "data-stonkks-codec";
+pub const VERSION_ATTRIBUTE: &str = "data-stonkks-version";
 pub const CLIENT_WASM_FILE_PATH: &str = "/public/stonkks_wasm_app.wasm";
 pub const CLIENT_JS_FILE_PATH: &str = "/public/stonkks_js_app.js";
 pub const ROOT_ELEMENT_ID: &str = "__STONKKS_ROOT__";
@@ -35,9 +43,16 @@ pub struct App {
     dyn_pages: DynPages,
     static_pages: StaticPages,
     api: ApiRoutes,
+    websockets: WebSocketRoutes,
+    middlewares: Middlewares,
     states: StatesMap,
     layout: AppLayout,
     not_found_page: NotFound,
+    error_page: ErrorPageComponent,
+    islands: Islands,
+    page_cache: PageCache,
+    props_codec: PropsCodec,
+    version: Option<String>,
 }
 
 impl App {
@@ -84,11 +99,80 @@ impl App {
         self
     }
 
+    /// Set the page rendered when a page or an api route fails.
+    pub fn error_page<T: ErrorPage>(mut self, error_page: T) -> Self {
+        self.error_page = error_page.into();
+        self
+    }
+
+    pub fn island<T: Island>(mut self, island: T) -> Self {
+        self.islands.add_island(island);
+        self
+    }
+
+    /// Set the codec used for the props of the pages, JSON by default.
+    /// Pages can override it with `Component::props_codec`.
+    pub fn props_codec(mut self, codec: PropsCodec) -> Self {
+        self.props_codec = codec;
+        self
+    }
+
+    /// Set the version of the app, embedded in the pages and checked against the version
+    /// of the client, so clients running an outdated wasm reload the page.
+    /// By default the version is computed from the props types and codecs of the pages,
+    /// which doesn't catch changes to the fields of the props, so setting it to a build id
+    /// (e.g. the commit hash) is recommended.
+    pub fn version<T: Into<String>>(mut self, version: T) -> Self {
+        self.version = Some(version.into());
+        self
+    }
+
+    /// Set the maximum number of entries kept in the cache of dynamic pages.
+    /// See `DynPage::cache_policy`.
+    pub fn page_cache_capacity(mut self, capacity: usize) -> Self {
+        self.page_cache = PageCache::new(capacity);
+        self
+    }
+
+    /// Add an api route, served under `/api/`.
+    /// Panics if the route serves OPTIONS, see `Api::methods`.
     pub fn api<T: Api>(mut self, api: T) -> Self {
         self.api.add_route(api);
         self
     }
 
+    /// Add a WebSocket route, served under `/ws/`.
+    pub fn websocket<T: WebSocketRoute>(mut self, route: T) -> Self {
+        self.websockets.add_route(route);
+        self
+    }
+
+    /// Set the CORS policy of the api routes, preflight requests are answered
+    /// before the middlewares run.
+    pub fn cors(mut self, cors: Cors) -> Self {
+        self.api.set_cors(cors);
+        self
+    }
+
+    /// Add a middleware running for every page and api request,
+    /// middlewares run in the order they were added.
+    pub fn middleware<T: Middleware>(self, middleware: T) -> Self {
+        self.middleware_for(RouteGroup::all(), middleware)
+    }
+
+    /// Add a middleware running for the routes of the group.
+    pub fn middleware_for<T: Middleware>(mut self, group: RouteGroup, middleware: T) -> Self {
+        self.middlewares.add(group, middleware);
+        self
+    }
+
+    /// Set the maximum size of the request body of the api routes, 1 MiB by default.
+    /// Routes can override it with `Api::body_limit`.
+    pub fn body_limit(mut self, limit: usize) -> Self {
+        self.api.set_body_limit(limit);
+        self
+    }
+
     pub fn apis<I>(mut self, apis: I) -> Self
     where
         I: IntoIterator<Item = Box<dyn DynApi>>,
@@ -106,7 +190,7 @@ impl App {
     }
 
     pub fn state_unwrap<T: Any + Send + Sync>(mut self, state: T) -> Self {
-        if let Some(_) = self.states.add_state(state) {
+        if self.states.add_state(state).is_some() {
             let name = std::any::type_name::<T>();
             panic!("This state was already present: {}.", name);
         } else {
@@ -115,29 +199,40 @@ impl App {
     }
 
     fn into_inner(self) -> AppInner {
-        AppInner {
+        let mut inner = AppInner {
             dyn_pages: self.dyn_pages,
             static_pages: self.static_pages,
             layout: self.layout,
             not_found_page: self.not_found_page,
-        }
+            error_page: self.error_page,
+            islands: self.islands,
+            props_codec: self.props_codec,
+            version: String::new(),
+        };
+        inner.version = self
+            .version
+            .unwrap_or_else(|| inner.props_schemas_version());
+        inner
     }
 
     pub fn into_client(self) -> Client {
         self.into_inner().into()
     }
 
-    pub fn into_server(self) -> Server {
-        let App {
-            dyn_pages,
-            static_pages,
+    pub fn into_server(mut self) -> Server {
+        let api = std::mem::take(&mut self.api);
+        let websockets = std::mem::take(&mut self.websockets);
+        let middlewares = std::mem::take(&mut self.middlewares);
+        let states = std::mem::take(&mut self.states);
+        let page_cache = std::mem::take(&mut self.page_cache);
+        Server::new(
+            self.into_inner(),
             api,
-            layout,
-            not_found_page,
+            websockets,
+            middlewares,
             states,
-        } = self;
-        let inner = AppInner::new(dyn_pages, static_pages, layout, not_found_page);
-        Server::new(inner, api, states)
+            page_cache,
+        )
     }
 }
 
@@ -146,42 +241,13 @@ pub struct AppInner {
     static_pages: StaticPages,
     layout: AppLayout,
     not_found_page: NotFound,
-}
-
-#[derive(Debug)]
-pub enum StaticGenerationError {
-    Io(std::io::Error),
-    User(String),
-    RouteMismatch(String),
-}
-
-impl From<std::io::Error> for StaticGenerationError {
-    fn from(value: std::io::Error) -> Self {
-        StaticGenerationError::Io(value)
-    }
-}
-
-impl From<String> for StaticGenerationError {
-    fn from(value: String) -> Self {
-        StaticGenerationError::User(value)
-    }
+    error_page: ErrorPageComponent,
+    islands: Islands,
+    props_codec: PropsCodec,
+    version: String,
 }
 
 impl AppInner {
-    pub fn new(
-        dyn_pages: DynPages,
-        static_pages: StaticPages,
-        layout: AppLayout,
-        not_found_page: NotFound,
-    ) -> Self {
-        AppInner {
-            dyn_pages,
-            static_pages,
-            layout,
-            not_found_page,
-        }
-    }
-
     pub fn dyn_pages(&self) -> &DynPages {
         &self.dyn_pages
     }
@@ -198,6 +264,50 @@ impl AppInner {
         &*self.not_found_page
     }
 
+    pub fn error_page(&self) -> &dyn DynComponent {
+        &*self.error_page
+    }
+
+    pub fn islands(&self) -> &Islands {
+        &self.islands
+    }
+
+    /// Codec used for the props of the pages not setting their own.
+    pub fn props_codec(&self) -> PropsCodec {
+        self.props_codec
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    /// Hash of the props types and codecs of all the components of the app.
+    /// Only strings are hashed, so the server and the wasm client compute the same version.
+    fn props_schemas_version(&self) -> String {
+        let static_pages = self.static_pages.iter_as_base_page();
+        let dyn_pages = self.dyn_pages.iter_as_base_page();
+        let pages = static_pages
+            .chain(dyn_pages)
+            .map(|page| page.as_dyn_component());
+        let special_pages = [self.not_found_page(), self.error_page()];
+        let mut schemas: Vec<_> = pages
+            .chain(special_pages)
+            .chain(self.islands.iter())
+            .map(|component| {
+                let codec = component.props_codec().unwrap_or(self.props_codec);
+                (component.props_type_name(), codec.name())
+            })
+            .collect();
+        // islands are stored in a map, the order must not change the version.
+        schemas.sort_unstable();
+        let mut hasher = DefaultHasher::new();
+        for (type_name, codec_name) in schemas {
+            type_name.hash(&mut hasher);
+            codec_name.hash(&mut hasher);
+        }
+        format!("{:x}", hasher.finish())
+    }
+
     fn get_static_page_folder_name(hashed_page_name: u64) -> String {
         format!("page_{:x}", hashed_page_name)
     }
@@ -221,8 +331,9 @@ impl AppInner {
         path.push("html.html");
     }
 
-    fn get_static_pages_props_path(path: &mut PathBuf) {
-        path.push("props.json");
+    /// The codec is part of the file name, so props generated with another codec are never read.
+    fn get_static_pages_props_path(path: &mut PathBuf, codec: PropsCodec) {
+        path.push(format!("props.{}", codec.name()));
     }
 
     async fn save_page(
@@ -230,6 +341,7 @@ impl AppInner {
         hashed_page_name: u64,
         hashed_route: u64,
         serialized_props: &str,
+        codec: PropsCodec,
     ) -> Result<(), std::io::Error> {
         let mut path = Self::get_static_pages_folder_path(hashed_page_name, hashed_route);
         fs::create_dir_all(&path).await?;
@@ -239,7 +351,7 @@ impl AppInner {
         page_html_file.write_all(page.as_bytes()).await?;
         page_html_file.flush().await?;
         path.pop();
-        Self::get_static_pages_props_path(&mut path);
+        Self::get_static_pages_props_path(&mut path, codec);
 
         let mut page_props_file = fs::File::create(&path).await?;
         page_props_file
@@ -252,7 +364,7 @@ impl AppInner {
         &self,
         page: &dyn DynStaticPage,
         states: &StatesMap,
-    ) -> Result<(), StaticGenerationError> {
+    ) -> Result<(), StonkksError> {
         let build_routes = page.get_build_routes(states).await?;
         let page_name = page.get_name();
         let mut hasher = DefaultHasher::new();
@@ -262,30 +374,101 @@ impl AppInner {
             let url_infos = OwnedUrlInfos::parse_from_url(&url);
             let page_and_route = StaticPageAndRoute::try_match_route(page, url_infos.to_shared());
             let Some(page_and_route) = page_and_route else {
-                return Err(StaticGenerationError::RouteMismatch(format!("Route {} was provided as build route, but did not match.", url)));
+                return Err(StonkksError::RouteMismatch(url.clone()));
             };
             let hashed_route = page_and_route.hash_route();
-            let page_and_props = page_and_route.get_props(states).await?;
+            let page_and_props = page_and_route.get_props(states, self.props_codec).await?;
             let serialized_props = page_and_props.serialize_props()?;
-            let html = sycamore::render_to_string(|cx| {
-                let DynRenderResult { body, head } = page_and_props.render_server(cx);
-                let body = self.layout().render_server(cx, body);
-                default_html_view(cx, body, head, &serialized_props, true)
-            });
-            let full_page = format!(
-                "<!DOCTYPE html><html id=\"{}\">{}</html>",
-                ROOT_ELEMENT_ID, html
-            );
-
-            Self::save_page(full_page, hashed_page_name, hashed_route, &serialized_props).await?;
+            let codec = page_and_props.props_codec();
+            let full_page = self.render_to_string(page_and_props, &serialized_props);
+
+            Self::save_page(
+                full_page,
+                hashed_page_name,
+                hashed_route,
+                &serialized_props,
+                codec,
+            )
+            .await?;
         }
         Ok(())
     }
 
-    pub async fn generate_static_pages(
+    /// Render the page to a full html document, according to the render mode of the page.
+    pub(crate) fn render_to_string(
         &self,
-        states: &StatesMap,
-    ) -> Result<(), StaticGenerationError> {
+        page_and_props: PageAndProps<'_>,
+        serialized_props: &str,
+    ) -> String {
+        let render_mode = page_and_props.render_mode();
+        self.render_to_string_with_mode(page_and_props, serialized_props, render_mode)
+    }
+
+    /// Render the error page for the given report.
+    /// The error page is always rendered on the server only, the client never hydrate it.
+    pub(crate) fn render_error_page(&self, report: &ErrorReport) -> String {
+        let props = ErrorPageProps::new(report);
+        let page_and_props =
+            PageAndProps::new(self.error_page(), props.to_untyped(), self.props_codec);
+        self.render_to_string_with_mode(page_and_props, "", RenderMode::ServerOnly)
+    }
+
+    fn render_to_string_with_mode(
+        &self,
+        page_and_props: PageAndProps<'_>,
+        serialized_props: &str,
+        render_mode: RenderMode,
+    ) -> String {
+        let props = Some(EmbeddedProps {
+            props: serialized_props,
+            codec: page_and_props.props_codec(),
+            version: self.version(),
+        });
+        let html = sycamore::render_to_string(|cx| {
+            provide_context(cx, ServerOnlyContext::default());
+            match render_mode {
+                RenderMode::Hydrate => {
+                    let DynRenderResult { body, head, .. } = page_and_props.render_server(cx);
+                    let body = self.layout().render_server(cx, body);
+                    let layout_head = self.layout().head();
+                    default_html_view(cx, body, layout_head, head, props, true)
+                }
+                RenderMode::ServerOnly => {
+                    let DynRenderResult { body, head, .. } = page_and_props.render_server(cx);
+                    let body = self.layout().render_server(cx, body);
+                    let layout_head = self.layout().head();
+                    default_html_view(cx, body, layout_head, head, None, false)
+                }
+                RenderMode::Islands => {
+                    // let the islands know they need to be rendered on their own.
+                    provide_context(cx, IslandsRender(self.props_codec));
+                    // the client only needs the props of the islands, stored on each island.
+                    let props = props.map(|props| EmbeddedProps { props: "", ..props });
+                    // only the islands are hydrated, each with its own hydration keys,
+                    // the keys of the page would collide with them.
+                    with_no_hydration_context(|| {
+                        let DynRenderResult { body, head, .. } = page_and_props.render_server(cx);
+                        let body = self.layout().render_server(cx, body);
+                        let layout_head = self.layout().head();
+                        default_html_view(cx, body, layout_head, head, props, true)
+                    })
+                }
+                RenderMode::ClientOnly => {
+                    // only send the layout as a shell, the client render the page in it.
+                    let head = create_signal(cx, Head::new());
+                    let body = self.layout().render_server(cx, View::empty());
+                    let layout_head = self.layout().head();
+                    default_html_view(cx, body, layout_head, head, props, true)
+                }
+            }
+        });
+        format!(
+            "<!DOCTYPE html><html id=\"{}\">{}</html>",
+            ROOT_ELEMENT_ID, html
+        )
+    }
+
+    pub async fn generate_static_pages(&self, states: &StatesMap) -> Result<(), StonkksError> {
         self.static_pages
             .iter()
             .map(|page| async { self.generate_page(page, states).await })
@@ -312,12 +495,13 @@ impl AppInner {
     pub async fn get_static_page_props(
         page_name: &str,
         hashed_route: u64,
+        codec: PropsCodec,
     ) -> Result<String, std::io::Error> {
         let mut hasher = DefaultHasher::new();
         page_name.hash(&mut hasher);
         let hashed_page_name = hasher.finish();
         let mut path = Self::get_static_pages_folder_path(hashed_page_name, hashed_route);
-        Self::get_static_pages_props_path(&mut path);
+        Self::get_static_pages_props_path(&mut path, codec);
         let mut page_html_file = fs::File::open(path).await?;
         let mut props = String::new();
         page_html_file.read_to_string(&mut props).await?;
@@ -325,25 +509,58 @@ impl AppInner {
     }
 }
 
-fn window_object_script(props: &str) -> String {
-    format!(
-        "window.{0}=window.{0}||{{}};window.{0}.{1}=\'{2}\';",
-        STONKKS_WINDOW_OBJECT_KEY, SERIALIZED_PROPS_KEY, props
-    )
+/// Serialized props embedded in the page, with what the client needs to read them.
+#[derive(Clone, Copy)]
+pub struct EmbeddedProps<'a> {
+    pub props: &'a str,
+    pub codec: PropsCodec,
+    pub version: &'a str,
+}
+
+/// Head elements present on every page, can be overriden by the layout or the page.
+fn base_head() -> Head {
+    Head::new().meta("viewport", "width=device-width, initial-scale=1.0")
 }
 
-fn default_head<G: Html>(cx: Scope, head: View<G>, props: &str) -> View<G> {
-    let script = window_object_script(props);
+/// The page head is rendered in a dynamic view, so on the client any change of the head signal
+/// of the page is reflected in the live document. On the server it is rendered once.
+/// Server only pages don't have props, so they don't load the client.
+fn default_head<'a, G: Html>(
+    cx: Scope<'a>,
+    layout_head: Head,
+    head: &'a ReadSignal<Head>,
+    props: Option<EmbeddedProps<'_>>,
+) -> View<G> {
+    let client_imports = match props {
+        Some(EmbeddedProps {
+            props,
+            codec,
+            version,
+        }) => {
+            // the props are never interpreted as javascript, and can't close the script tag.
+            let props = escape_json_for_script(props);
+            let codec = codec.name();
+            let version = version.to_owned();
+            view! { cx,
+                link(rel="preload", href=CLIENT_WASM_FILE_PATH, as="fetch", type="application/wasm", crossorigin="")
+                link(rel="modulepreload", href=CLIENT_JS_FILE_PATH)
+                script(type="application/json", id=PROPS_ELEMENT_ID, data-stonkks-codec=codec, data-stonkks-version=version, dangerously_set_inner_html=&props)
+            }
+        }
+        None => view! { cx, },
+    };
+    let head = View::new_dyn(cx, move || {
+        let page_head = Head::clone(&head.get());
+        base_head()
+            .merge(layout_head.clone())
+            .merge(page_head)
+            .render(cx)
+    });
     view! { cx,
         head {
             meta(charset = "UTF-8")
             meta(http-equiv="X-UA-Compatible", content="IE=edge")
-            meta(name="viewport", content="width=device-width, initial-scale=1.0")
-            link(rel="preload", href=CLIENT_WASM_FILE_PATH, as="fetch", type="application/wasm", crossorigin="")
-            link(rel="modulepreload", href=CLIENT_JS_FILE_PATH)
-            script {
-                (script)
-            }
+            (client_imports)
             (head)
         }
     }
@@ -351,14 +568,15 @@ fn default_head<G: Html>(cx: Scope, head: View<G>, props: &str) -> View<G> {
 
 /// the render imports argument is for client rendering, sycamore::render re-render everyhting, so it re-init the wasm file
 /// and start an infinite loop.
-pub fn default_html_view<G: Html>(
-    cx: Scope,
+pub fn default_html_view<'a, G: Html>(
+    cx: Scope<'a>,
     body: View<G>,
-    head: View<G>,
-    props: &str,
+    layout_head: Head,
+    head: &'a ReadSignal<Head>,
+    props: Option<EmbeddedProps<'_>>,
     render_imports: bool,
 ) -> View<G> {
-    let head = default_head(cx, head, props);
+    let head = default_head(cx, layout_head, head, props);
     view! { cx,
         (head)
         body {
@@ -0,0 +1,195 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use stonkks_core::cache::CachePolicy;
+use stonkks_core::request::{Cookies, Headers};
+use stonkks_core::routes::UrlInfos;
+
+pub const DEFAULT_CACHE_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct CacheKey {
+    page_name: &'static str,
+    route_hash: u64,
+    vary_hash: u64,
+}
+
+impl CacheKey {
+    pub fn new(
+        page_name: &'static str,
+        route_hash: u64,
+        policy: &CachePolicy,
+        url_infos: UrlInfos<'_, '_>,
+        headers: &Headers,
+    ) -> Self {
+        let mut hasher = DefaultHasher::new();
+        for name in policy.vary_params() {
+            let value = url_infos.params().and_then(|params| params.get(name));
+            value.hash(&mut hasher);
+        }
+        for name in policy.vary_headers() {
+            let values: Vec<&str> = headers.get_all(name).collect();
+            values.hash(&mut hasher);
+        }
+        if !policy.vary_cookies().is_empty() {
+            let cookies = Cookies::parse(headers);
+            for name in policy.vary_cookies() {
+                cookies.get(name).hash(&mut hasher);
+            }
+        }
+        CacheKey {
+            page_name,
+            route_hash,
+            vary_hash: hasher.finish(),
+        }
+    }
+
+    /// Hash of the url parameters, headers and cookies listed in the policy.
+    pub fn vary_hash(&self) -> u64 {
+        self.vary_hash
+    }
+}
+
+struct CacheEntry {
+    serialized_props: String,
+    html: Option<String>,
+    expires_at: Instant,
+    last_used: u64,
+}
+
+struct CacheInner {
+    entries: HashMap<CacheKey, CacheEntry>,
+    capacity: usize,
+    // monotonic counter used to track the least recently used entry.
+    clock: u64,
+}
+
+impl CacheInner {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get_entry(&mut self, key: &CacheKey) -> Option<&mut CacheEntry> {
+        let now = Instant::now();
+        let expired = self.entries.get(key)?.expires_at <= now;
+        if expired {
+            self.entries.remove(key);
+            return None;
+        }
+        let tick = self.tick();
+        let entry = self.entries.get_mut(key)?;
+        entry.last_used = tick;
+        Some(entry)
+    }
+
+    fn evict(&mut self) {
+        let now = Instant::now();
+        self.entries.retain(|_, entry| entry.expires_at > now);
+        while self.entries.len() >= self.capacity {
+            let lru_key = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| *key);
+            match lru_key {
+                Some(key) => self.entries.remove(&key),
+                None => break,
+            };
+        }
+    }
+
+    fn insert(&mut self, key: CacheKey, ttl: Duration, props: String, html: Option<String>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.entries.contains_key(&key) {
+            self.evict();
+        }
+        let last_used = self.tick();
+        let entry = CacheEntry {
+            serialized_props: props,
+            html,
+            expires_at: Instant::now() + ttl,
+            last_used,
+        };
+        self.entries.insert(key, entry);
+    }
+}
+
+/// Size bounded LRU cache of the responses of dynamic pages.
+pub struct PageCache(Mutex<CacheInner>);
+
+impl Default for PageCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+impl PageCache {
+    pub fn new(capacity: usize) -> Self {
+        let inner = CacheInner {
+            entries: HashMap::new(),
+            capacity,
+            clock: 0,
+        };
+        PageCache(Mutex::new(inner))
+    }
+
+    fn with_inner<R>(&self, f: impl FnOnce(&mut CacheInner) -> R) -> R {
+        // a panic while holding the lock can't leave the cache in an invalid state.
+        let mut inner = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        f(&mut inner)
+    }
+
+    pub(crate) fn get_html(&self, key: &CacheKey) -> Option<String> {
+        self.with_inner(|inner| inner.get_entry(key)?.html.clone())
+    }
+
+    pub(crate) fn get_props(&self, key: &CacheKey) -> Option<String> {
+        self.with_inner(|inner| {
+            inner
+                .get_entry(key)
+                .map(|entry| entry.serialized_props.clone())
+        })
+    }
+
+    pub(crate) fn insert(
+        &self,
+        key: CacheKey,
+        ttl: Duration,
+        serialized_props: String,
+        html: Option<String>,
+    ) {
+        self.with_inner(|inner| inner.insert(key, ttl, serialized_props, html))
+    }
+
+    /// Remove all the entries of the given page.
+    pub fn invalidate_page(&self, page_name: &str) {
+        self.with_inner(|inner| inner.entries.retain(|key, _| key.page_name != page_name))
+    }
+
+    /// Remove all the entries for the given route of a page, whatever the vary parameters.
+    pub fn invalidate_route(&self, page_name: &str, route_hash: u64) {
+        self.with_inner(|inner| {
+            inner
+                .entries
+                .retain(|key, _| key.page_name != page_name || key.route_hash != route_hash)
+        })
+    }
+
+    pub fn clear(&self) {
+        self.with_inner(|inner| inner.entries.clear())
+    }
+
+    pub fn len(&self) -> usize {
+        self.with_inner(|inner| inner.entries.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
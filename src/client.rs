@@ -1,46 +1,98 @@
 use crate::app::{
-    default_html_view, AppInner, ROOT_ELEMENT_ID, SERIALIZED_PROPS_KEY, STONKKS_WINDOW_OBJECT_KEY,
+    default_html_view, AppInner, EmbeddedProps, PROPS_CODEC_ATTRIBUTE, PROPS_ELEMENT_ID,
+    ROOT_ELEMENT_ID, VERSION_ATTRIBUTE,
 };
+use crate::islands::Islands;
 use crate::pages::StaticPages;
+use crate::server::WS_ROUTE_SEGMENT;
 use crate::utils::PageAndProps;
 
 use super::pages::DynPages;
 use super::prelude::*;
-use js_sys::{JsString, Object};
-use serde_json::Error;
+use js_sys::JsString;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::rc::Rc;
+use stonkks_core::codec::{CodecError, PropsCodec};
+use stonkks_core::islands::{ISLAND_NAME_ATTRIBUTE, ISLAND_PROPS_ATTRIBUTE};
 use stonkks_core::layout::DynLayout;
-use stonkks_core::pages::{DynBasePage, DynComponent, DynRenderResult};
+use stonkks_core::pages::{DynBasePage, DynComponent, DynRenderResult, DynSnapshot};
 use stonkks_core::routes::UrlInfos;
-use wasm_bindgen::{throw_str, JsValue};
-use web_sys::{Element, Window};
+use stonkks_core::server_only::{ServerOnlyContext, SERVER_ONLY_ATTRIBUTE};
+use stonkks_core::websocket::{encode_message, WebSocketRoute, WsError, WsMessage};
+use sycamore::prelude::{create_effect, provide_context, Scope};
+use wasm_bindgen::closure::Closure;
+use wasm_bindgen::{throw_str, JsCast, JsValue};
+use web_sys::{
+    BinaryType, Document, Element, MessageEvent, NavigationType, PerformanceNavigationTiming,
+    Window,
+};
 
 fn log(msg: &str) {
     let s = JsString::from(msg);
     web_sys::console::log_1(&s);
 }
 
-enum StartupError {
+pub(crate) enum StartupError {
     NoWindow,
     NoProps,
     NoPathname,
-    NoStonkksObject,
-    PropsNotUTF8,
+    NoDocument,
+    UnknownPropsCodec,
+    PropsCodecMismatch,
+    VersionMismatch,
+    InvalidIslandProps,
+    InvalidProps,
+    NoRootElement,
+    ServerOnlyMismatch,
 }
 
 impl StartupError {
     pub fn error_msg(self) -> &'static str {
         match self {
             StartupError::NoWindow => "Unable to aquire the window object.",
-            StartupError::NoProps => "No props present in the Stonkks object.",
+            StartupError::NoProps => "No props element present in the document.",
             StartupError::NoPathname => "Unable to get the pathname.",
-            StartupError::NoStonkksObject => "No Stonkks object.",
-            StartupError::PropsNotUTF8 => "Props are not UTF8 encoded.",
+            StartupError::NoDocument => "Unable to aquire the document.",
+            StartupError::UnknownPropsCodec => {
+                "Unknown props codec, is the codec feature enabled on the client ?"
+            }
+            StartupError::PropsCodecMismatch => {
+                "The props codec of the server does not match the one of the client."
+            }
+            StartupError::VersionMismatch => {
+                "The client is outdated and reloading the page did not update it."
+            }
+            StartupError::InvalidIslandProps => "Error appened deserializing the island props.",
+            StartupError::InvalidProps => {
+                "Error appened deserializing the props and reloading the page did not fix it."
+            }
+            StartupError::NoRootElement => "No root element present in the document.",
+            StartupError::ServerOnlyMismatch => {
+                "The server only parts of the page do not match the ones rendered by the server."
+            }
         }
     }
 }
 
 type StartupResult<T> = Result<T, StartupError>;
 
+/// Latest serialized snapshot of the page props.
+type LatestSnapshot = Rc<RefCell<Option<String>>>;
+
+/// Snapshot of the page saved in the history state.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    url: String,
+    version: String,
+    codec: String,
+    props: String,
+}
+
+const RELOADED_VERSION_KEY: &str = "__STONKKS_RELOADED_VERSION__";
+
 pub struct Client {
     inner: AppInner,
 }
@@ -68,6 +120,14 @@ impl Client {
         self.inner.layout()
     }
 
+    fn islands(&self) -> &Islands {
+        self.inner.islands()
+    }
+
+    fn props_codec(&self) -> PropsCodec {
+        self.inner.props_codec()
+    }
+
     fn find_any_page<'inf, 'url, 'a, I: IntoIterator<Item = &'a dyn DynBasePage>>(
         pages: I,
         url_infos: UrlInfos<'inf, 'url>,
@@ -85,63 +145,281 @@ impl Client {
         Self::find_any_page(iter_pages, url_infos).unwrap_or(self.not_found_page())
     }
 
+    fn find_render_mode(&self, url: &str) -> RenderMode {
+        let url_infos = OwnedUrlInfos::parse_from_url(url);
+        self.find_page(url_infos.to_shared()).render_mode()
+    }
+
+    fn find_props_codec(&self, url: &str) -> PropsCodec {
+        let url_infos = OwnedUrlInfos::parse_from_url(url);
+        let page = self.find_page(url_infos.to_shared());
+        page.props_codec().unwrap_or(self.props_codec())
+    }
+
     fn find_page_and_props<'a, 'url>(
         &self,
         url_infos: UrlInfos<'a, 'url>,
         serialized_props: &str,
-    ) -> Result<PageAndProps<'_>, Error> {
+    ) -> Result<PageAndProps<'_>, CodecError> {
         let page = self.find_page(url_infos);
-        PageAndProps::deserialize(page, serialized_props)
+        PageAndProps::deserialize(page, serialized_props, self.props_codec())
     }
 
-    fn prepare_render<'url>(
+    fn prepare_render(
         &self,
-        url: &'url str,
+        url: &str,
         serialized_props: &str,
-    ) -> (PageAndProps<'_>, Element) {
+    ) -> StartupResult<(PageAndProps<'_>, Element)> {
         let url_infos = OwnedUrlInfos::parse_from_url(url);
         let url_infos = url_infos.to_shared();
         let page_and_props = self
             .find_page_and_props(url_infos, serialized_props)
-            .expect("Error appened deserializing the props");
+            .map_err(|_| StartupError::InvalidProps)?;
 
-        let root = web_sys::window()
-            .unwrap()
-            .document()
-            .unwrap()
+        let root = Self::get_document()?
             .query_selector(&format!("#{}", ROOT_ELEMENT_ID))
-            .unwrap()
-            .unwrap();
+            .ok()
+            .flatten()
+            .ok_or(StartupError::NoRootElement)?;
 
-        (page_and_props, root)
+        Ok((page_and_props, root))
     }
 
-    pub fn render<'url>(&self, url: &'url str, serialized_props: &str) {
-        let (page_and_props, root) = self.prepare_render(url, serialized_props);
+    /// Render the page on the client, errors are logged. See `Client::run`.
+    pub fn render(&self, url: &str, serialized_props: &str) {
+        if let Err(err) = self.try_render(url, serialized_props) {
+            log(err.error_msg());
+        }
+    }
+
+    fn try_render(&self, url: &str, serialized_props: &str) -> StartupResult<()> {
+        let (page_and_props, root) = self.prepare_render(url, serialized_props)?;
+        let props = EmbeddedProps {
+            props: serialized_props,
+            codec: page_and_props.props_codec(),
+            version: self.inner.version(),
+        };
+        let server_only_contents = Self::server_only_contents();
+        let latest_snapshot = LatestSnapshot::default();
 
         root.set_inner_html("");
 
         sycamore::render_to(
             |cx| {
-                let DynRenderResult { body, head } = page_and_props.render_client(cx);
+                provide_context(cx, ServerOnlyContext::new(server_only_contents));
+                let DynRenderResult {
+                    body,
+                    head,
+                    snapshot,
+                } = page_and_props.render_client(cx);
+                Self::track_snapshot(cx, snapshot, props.codec, &latest_snapshot);
                 let body = self.layout().render_client(cx, body);
-                default_html_view(cx, body, head, &serialized_props, false)
+                default_html_view(cx, body, self.layout().head(), head, Some(props), false)
             },
             &root,
-        )
+        );
+        self.save_snapshot_on_leave(url, props.codec, latest_snapshot);
+        Ok(())
+    }
+
+    /// Hydrate the page rendered by the server, errors are logged. See `Client::run`.
+    pub fn hydrate(&self, url: &str, serialized_props: &str) {
+        if let Err(err) = self.try_hydrate(url, serialized_props) {
+            log(err.error_msg());
+        }
     }
 
-    pub fn hydrate<'url>(&self, url: &'url str, serialized_props: &str) {
-        let (page_and_props, root) = self.prepare_render(url, serialized_props);
+    fn try_hydrate(&self, url: &str, serialized_props: &str) -> StartupResult<()> {
+        let (page_and_props, root) = self.prepare_render(url, serialized_props)?;
+        let props = EmbeddedProps {
+            props: serialized_props,
+            codec: page_and_props.props_codec(),
+            version: self.inner.version(),
+        };
+        let server_only = ServerOnlyContext::new(Self::server_only_contents());
+        let latest_snapshot = LatestSnapshot::default();
 
         sycamore::hydrate_to(
             |cx| {
-                let DynRenderResult { body, head } = page_and_props.hydrate(cx);
+                provide_context(cx, server_only.clone());
+                let DynRenderResult {
+                    body,
+                    head,
+                    snapshot,
+                } = page_and_props.hydrate(cx);
+                Self::track_snapshot(cx, snapshot, props.codec, &latest_snapshot);
                 let body = self.layout().hydrate(cx, body);
-                default_html_view(cx, body, head, &serialized_props, false)
+                default_html_view(cx, body, self.layout().head(), head, Some(props), false)
             },
             &root,
-        )
+        );
+        self.save_snapshot_on_leave(url, props.codec, latest_snapshot);
+        server_only.check_hydrated().map_err(|err| {
+            log(&err);
+            StartupError::ServerOnlyMismatch
+        })
+    }
+
+    /// Keep the latest serialized snapshot of the page, see `Component::snapshot_props`.
+    fn track_snapshot<'a>(
+        cx: Scope<'a>,
+        snapshot: Option<DynSnapshot<'a>>,
+        codec: PropsCodec,
+        latest_snapshot: &LatestSnapshot,
+    ) {
+        let Some(snapshot) = snapshot else {
+            return;
+        };
+        let latest_snapshot = Rc::clone(latest_snapshot);
+        create_effect(cx, move || {
+            *latest_snapshot.borrow_mut() = snapshot(codec).ok();
+        });
+    }
+
+    /// Save the latest snapshot in the history state when leaving the page,
+    /// so it can be restored on back navigation.
+    fn save_snapshot_on_leave(
+        &self,
+        url: &str,
+        codec: PropsCodec,
+        latest_snapshot: LatestSnapshot,
+    ) {
+        if latest_snapshot.borrow().is_none() {
+            // the page does not support snapshots.
+            return;
+        }
+        let Ok(window) = Self::get_window() else {
+            return;
+        };
+        let url = url.to_owned();
+        let version = self.inner.version().to_owned();
+        let save = move || {
+            let Some(props) = latest_snapshot.borrow().clone() else {
+                return;
+            };
+            let snapshot = Snapshot {
+                url: url.clone(),
+                version: version.clone(),
+                codec: codec.name().to_owned(),
+                props,
+            };
+            let history = Self::get_window()
+                .ok()
+                .and_then(|window| window.history().ok());
+            if let (Some(history), Ok(state)) = (history, serde_json::to_string(&snapshot)) {
+                let _ = history.replace_state(&JsValue::from_str(&state), "");
+            }
+        };
+        let listener = Closure::wrap(Box::new(save) as Box<dyn Fn()>);
+        let _ =
+            window.add_event_listener_with_callback("pagehide", listener.as_ref().unchecked_ref());
+        // the listener lives as long as the page.
+        listener.forget();
+    }
+
+    /// Whether the page was loaded by going back or forward in the history.
+    fn is_back_forward_navigation() -> bool {
+        let Some(performance) = Self::get_window()
+            .ok()
+            .and_then(|window| window.performance())
+        else {
+            return false;
+        };
+        performance
+            .get_entries_by_type("navigation")
+            .get(0)
+            .dyn_into::<PerformanceNavigationTiming>()
+            .is_ok_and(|timing| timing.type_() == NavigationType::BackForward)
+    }
+
+    /// Serialized props saved in the history state for this page, if any.
+    /// The history state survives a reload, so the snapshot is only restored when
+    /// going back or forward to the page, a reload fetches fresh props.
+    fn restore_snapshot(&self, url: &str) -> Option<String> {
+        if !Self::is_back_forward_navigation() {
+            return None;
+        }
+        let state = Self::get_window().ok()?.history().ok()?.state().ok()?;
+        let snapshot: Snapshot = serde_json::from_str(&state.as_string()?).ok()?;
+        let codec = self.find_props_codec(url);
+        let is_valid = snapshot.url == url
+            && snapshot.version == self.inner.version()
+            && snapshot.codec == codec.name();
+        is_valid.then_some(snapshot.props)
+    }
+
+    /// Html rendered by the server for each server only part of the page, by key.
+    /// See `ServerOnly`.
+    fn server_only_contents() -> HashMap<String, String> {
+        let selector = format!("[{}]", SERVER_ONLY_ATTRIBUTE);
+        let elements = Self::get_document()
+            .ok()
+            .and_then(|document| document.query_selector_all(&selector).ok());
+        let Some(elements) = elements else {
+            return HashMap::new();
+        };
+        (0..elements.length())
+            .filter_map(|index| elements.item(index)?.dyn_into::<Element>().ok())
+            .filter_map(|element| {
+                let key = element.get_attribute(SERVER_ONLY_ATTRIBUTE)?;
+                Some((key, element.inner_html()))
+            })
+            .collect()
+    }
+
+    fn hydrate_island(&self, element: &Element) -> StartupResult<()> {
+        let name = element.get_attribute(ISLAND_NAME_ATTRIBUTE);
+        let serialized_props = element.get_attribute(ISLAND_PROPS_ATTRIBUTE);
+        let (Some(name), Some(serialized_props)) = (name, serialized_props) else {
+            log("Malformed island, skipping it.");
+            return Ok(());
+        };
+        let Some(island) = self.islands().get(&name) else {
+            log("Unknown island, did you forget to register it ?");
+            log(&name);
+            return Ok(());
+        };
+        let codec = island.props_codec().unwrap_or(self.props_codec());
+        let props = island
+            .deserialize_props(&serialized_props, codec)
+            .map_err(|_| StartupError::InvalidIslandProps)?;
+
+        sycamore::hydrate_to(|cx| unsafe { island.hydrate(cx, props) }.body, element);
+
+        // every island is rendered on the server with its own hydration keys,
+        // and sycamore looks them up in the whole document, so the keys of an hydrated
+        // island are removed for the next islands to find theirs.
+        let hydrated = element
+            .query_selector_all("[data-hk]")
+            .map_err(|_| StartupError::NoDocument)?;
+        for index in 0..hydrated.length() {
+            if let Some(element) = hydrated
+                .item(index)
+                .and_then(|node| node.dyn_into::<Element>().ok())
+            {
+                let _ = element.remove_attribute("data-hk");
+            }
+        }
+        Ok(())
+    }
+
+    /// Hydrate all the islands present in the document,
+    /// the rest of the page is left untouched.
+    pub(crate) fn hydrate_islands(&self) -> StartupResult<()> {
+        let document = Self::get_document()?;
+        let selector = format!("[{}]", ISLAND_NAME_ATTRIBUTE);
+        let islands = document
+            .query_selector_all(&selector)
+            .map_err(|_| StartupError::NoDocument)?;
+        for index in 0..islands.length() {
+            let element = islands
+                .item(index)
+                .and_then(|node| node.dyn_into::<Element>().ok());
+            if let Some(element) = element {
+                self.hydrate_island(&element)?;
+            }
+        }
+        Ok(())
     }
 
     fn get_window() -> StartupResult<Window> {
@@ -155,18 +433,60 @@ impl Client {
             .map_err(|_| StartupError::NoPathname)
     }
 
-    fn get_stonkks_object() -> StartupResult<Object> {
+    fn get_document() -> StartupResult<Document> {
         Self::get_window()?
-            .get(STONKKS_WINDOW_OBJECT_KEY)
-            .ok_or(StartupError::NoStonkksObject)
+            .document()
+            .ok_or(StartupError::NoDocument)
+    }
+
+    /// The props are embedded in a `<script type="application/json">` element,
+    /// the text content of the element is the serialized props.
+    fn get_props_element() -> StartupResult<Element> {
+        Self::get_document()?
+            .get_element_by_id(PROPS_ELEMENT_ID)
+            .ok_or(StartupError::NoProps)
     }
 
     fn get_serialized_props() -> StartupResult<String> {
-        let window_object: JsValue = Self::get_stonkks_object()?.into();
-        let props_key = js_sys::JsString::from(SERIALIZED_PROPS_KEY);
-        let props_string =
-            js_sys::Reflect::get(&window_object, &props_key).map_err(|_| StartupError::NoProps)?;
-        props_string.as_string().ok_or(StartupError::PropsNotUTF8)
+        Self::get_props_element()?
+            .text_content()
+            .ok_or(StartupError::NoProps)
+    }
+
+    fn get_props_codec() -> StartupResult<PropsCodec> {
+        let codec_name = Self::get_props_element()?
+            .get_attribute(PROPS_CODEC_ATTRIBUTE)
+            .ok_or(StartupError::UnknownPropsCodec)?;
+        PropsCodec::from_name(&codec_name).ok_or(StartupError::UnknownPropsCodec)
+    }
+
+    fn get_server_version() -> StartupResult<Option<String>> {
+        let version = Self::get_props_element()?.get_attribute(VERSION_ATTRIBUTE);
+        Ok(version)
+    }
+
+    /// Reload the page to fetch the new version of the client, called when the page
+    /// was rendered by another version of the app.
+    /// The page is reloaded only once per server version, to not loop if the outdated
+    /// client is still served from a cache.
+    fn reload_outdated(&self, server_version: &str) -> bool {
+        let Ok(window) = Self::get_window() else {
+            return false;
+        };
+        let storage = window.session_storage().ok().flatten();
+        if let Some(storage) = &storage {
+            if storage
+                .get_item(RELOADED_VERSION_KEY)
+                .ok()
+                .flatten()
+                .as_deref()
+                == Some(server_version)
+            {
+                return false;
+            }
+            let _ = storage.set_item(RELOADED_VERSION_KEY, server_version);
+        }
+        window.location().reload().is_ok()
     }
 
     fn get_url_and_props() -> StartupResult<(String, String)> {
@@ -181,11 +501,68 @@ impl Client {
         log(&url);
         log("props: ");
         log(&serialized_props);
-        log("start hydrate.");
-        self.hydrate(&url, &serialized_props);
-        // self.render(&url, &serialized_props);
-        log("hydrate finished.");
-        Ok(())
+        let render_mode = self.find_render_mode(&url);
+        if render_mode != RenderMode::ServerOnly {
+            if let Some(server_version) = Self::get_server_version()? {
+                if server_version != self.inner.version() {
+                    log("outdated client, reloading the page.");
+                    if self.reload_outdated(&server_version) {
+                        return Ok(());
+                    }
+                    return Err(StartupError::VersionMismatch);
+                }
+            }
+            // the server and the client must agree on the codec of the page props.
+            let codec = Self::get_props_codec()?;
+            if codec != self.find_props_codec(&url) {
+                return Err(StartupError::PropsCodecMismatch);
+            }
+        }
+        if let Some(snapshot) = self.restore_snapshot(&url) {
+            if matches!(render_mode, RenderMode::Hydrate | RenderMode::ClientOnly) {
+                // the snapshot can differ from the server render, so the page is fully rendered.
+                log("restore snapshot.");
+                match self.try_render(&url, &snapshot) {
+                    Ok(()) => return Ok(()),
+                    Err(err) => log(err.error_msg()),
+                }
+            }
+        }
+        let result = match render_mode {
+            RenderMode::Hydrate => {
+                log("start hydrate.");
+                self.try_hydrate(&url, &serialized_props)
+            }
+            RenderMode::ClientOnly => {
+                log("start render.");
+                self.try_render(&url, &serialized_props)
+            }
+            RenderMode::Islands => {
+                log("start islands hydrate.");
+                self.hydrate_islands()
+            }
+            RenderMode::ServerOnly => {
+                log("server only page, nothing to render.");
+                return Ok(());
+            }
+        };
+        match result {
+            // props the client can't decode were rendered by another version of the app,
+            // like with a version mismatch the page is reloaded to fetch the matching client.
+            Err(StartupError::InvalidProps) => {
+                log("invalid props, reloading the page.");
+                let server_version = Self::get_server_version()?.unwrap_or_default();
+                if self.reload_outdated(&server_version) {
+                    return Ok(());
+                }
+                Err(StartupError::InvalidProps)
+            }
+            Err(err) => Err(err),
+            Ok(()) => {
+                log("render finished.");
+                Ok(())
+            }
+        }
     }
 
     pub fn run(&self) {
@@ -194,3 +571,143 @@ impl Client {
         }
     }
 }
+
+/// Event listener registered on the socket, removed on drop.
+type SocketListener = (&'static str, Closure<dyn FnMut(MessageEvent)>);
+
+/// Typed connection from the browser to a `WebSocketRoute`, see `Client::connect_websocket`.
+/// Messages sent before the connection is open are queued, the connection is closed on drop.
+pub struct ClientSocket<T: WebSocketRoute> {
+    socket: web_sys::WebSocket,
+    codec: PropsCodec,
+    pending: Rc<RefCell<Vec<WsMessage>>>,
+    listeners: RefCell<Vec<SocketListener>>,
+    _marker: PhantomData<T>,
+}
+
+impl Client {
+    /// Connect to the `WebSocketRoute` matching the path, relative to `/ws/`.
+    /// Only available in the browser, so it is usually called from an effect.
+    ///
+    /// ```ignore
+    /// let socket = Client::connect_websocket::<ChatRoute>("chat/general")?;
+    /// socket.on_message(move |message| messages.modify().push(message));
+    /// socket.send(&ChatMessage::Join)?;
+    /// ```
+    pub fn connect_websocket<T: WebSocketRoute>(path: &str) -> Result<ClientSocket<T>, WsError> {
+        let location = web_sys::window().ok_or(WsError::Closed)?.location();
+        let scheme = match location.protocol() {
+            Ok(protocol) if protocol == "https:" => "wss:",
+            _ => "ws:",
+        };
+        let host = location.host().map_err(js_transport_error)?;
+        let url = format!(
+            "{}//{}/{}/{}",
+            scheme,
+            host,
+            WS_ROUTE_SEGMENT,
+            path.trim_start_matches('/')
+        );
+        let socket = web_sys::WebSocket::new(&url).map_err(js_transport_error)?;
+        socket.set_binary_type(BinaryType::Arraybuffer);
+        let client_socket = ClientSocket {
+            socket,
+            codec: T::codec(),
+            pending: Rc::default(),
+            listeners: RefCell::default(),
+            _marker: PhantomData,
+        };
+        client_socket.flush_on_open();
+        Ok(client_socket)
+    }
+}
+
+fn js_transport_error(err: JsValue) -> WsError {
+    WsError::Transport(format!("{:?}", err))
+}
+
+impl<T: WebSocketRoute> ClientSocket<T> {
+    fn add_listener(&self, event: &'static str, listener: Closure<dyn FnMut(MessageEvent)>) {
+        let _ = self
+            .socket
+            .add_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+        self.listeners.borrow_mut().push((event, listener));
+    }
+
+    fn flush_on_open(&self) {
+        let socket = self.socket.clone();
+        let pending = self.pending.clone();
+        let listener = Closure::wrap(Box::new(move |_: MessageEvent| {
+            for message in pending.borrow_mut().drain(..) {
+                let _ = send_message(&socket, message);
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        self.add_listener("open", listener);
+    }
+
+    pub fn send(&self, message: &T::Incoming) -> Result<(), WsError> {
+        let message = encode_message(self.codec, message)?;
+        match self.socket.ready_state() {
+            web_sys::WebSocket::CONNECTING => {
+                self.pending.borrow_mut().push(message);
+                Ok(())
+            }
+            web_sys::WebSocket::OPEN => send_message(&self.socket, message),
+            _ => Err(WsError::Closed),
+        }
+    }
+
+    /// Call `callback` with each message of the server,
+    /// messages that can't be decoded are skipped.
+    pub fn on_message<F: FnMut(T::Outgoing) + 'static>(&self, mut callback: F) {
+        let codec = self.codec;
+        let listener = Closure::wrap(Box::new(move |event: MessageEvent| {
+            let data = event.data();
+            let decoded = if let Some(text) = data.as_string() {
+                codec.decode_bytes(text.as_bytes())
+            } else if let Some(buffer) = data.dyn_ref::<js_sys::ArrayBuffer>() {
+                codec.decode_bytes(&js_sys::Uint8Array::new(buffer).to_vec())
+            } else {
+                return;
+            };
+            match decoded {
+                Ok(message) => callback(message),
+                Err(err) => log(&format!("invalid websocket message: {}", err)),
+            }
+        }) as Box<dyn FnMut(MessageEvent)>);
+        self.add_listener("message", listener);
+    }
+
+    /// Call `callback` when the connection is closed, by either side.
+    pub fn on_close<F: FnMut() + 'static>(&self, mut callback: F) {
+        let listener = Closure::wrap(
+            Box::new(move |_: MessageEvent| callback()) as Box<dyn FnMut(MessageEvent)>
+        );
+        self.add_listener("close", listener);
+    }
+
+    pub fn close(&self) {
+        let _ = self.socket.close();
+    }
+}
+
+fn send_message(socket: &web_sys::WebSocket, message: WsMessage) -> Result<(), WsError> {
+    let result = match message {
+        WsMessage::Text(text) => socket.send_with_str(&text),
+        WsMessage::Binary(bytes) => socket.send_with_u8_array(&bytes),
+        WsMessage::Close => socket.close(),
+    };
+    result.map_err(js_transport_error)
+}
+
+impl<T: WebSocketRoute> Drop for ClientSocket<T> {
+    fn drop(&mut self) {
+        self.close();
+        // the closures are dropped with the socket, the browser must not call them anymore.
+        for (event, listener) in self.listeners.get_mut().drain(..) {
+            let _ = self
+                .socket
+                .remove_event_listener_with_callback(event, listener.as_ref().unchecked_ref());
+        }
+    }
+}
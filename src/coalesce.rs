@@ -0,0 +1,117 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use futures::channel::oneshot;
+use stonkks_core::errors::StonkksError;
+use stonkks_core::pointers::PropsUntypedPtr;
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+/// Result of a load, shared with the coalesced requests.
+/// The loaded props are kept until a request needs the html of the page,
+/// the first one renders it from them and the others reuse its html.
+pub(crate) struct SharedLoad {
+    pub serialized_props: String,
+    props: Mutex<Option<PropsUntypedPtr>>,
+    html: Mutex<Option<String>>,
+}
+
+impl SharedLoad {
+    pub fn new(serialized_props: String, props: PropsUntypedPtr) -> Self {
+        SharedLoad {
+            serialized_props,
+            props: Mutex::new(Some(props)),
+            html: Mutex::new(None),
+        }
+    }
+
+    /// Html of the page, rendered from the loaded props by the first request asking for it.
+    /// `None` if that render panicked, the props being lost with it.
+    pub fn html(&self, render: impl FnOnce(PropsUntypedPtr) -> String) -> Option<String> {
+        let mut html = lock(&self.html);
+        if html.is_none() {
+            let props = lock(&self.props).take()?;
+            *html = Some(render(props));
+        }
+        html.clone()
+    }
+}
+type LoadResult = Result<Arc<SharedLoad>, StonkksError>;
+type InFlightKey = (&'static str, u64, u64);
+type Waiters = Vec<oneshot::Sender<LoadResult>>;
+
+/// Props loads currently running, keyed by page name, route hash and the hash of the
+/// request data the page varies on (see `CacheKey`).
+#[derive(Default)]
+pub struct InFlightLoads(Mutex<HashMap<InFlightKey, Waiters>>);
+
+pub(crate) enum Coalesced<'a> {
+    /// No load is running for this key, the caller must run it and complete the guard.
+    Leader(LeaderGuard<'a>),
+    /// A load is already running, the result will be sent on the receiver.
+    Follower(oneshot::Receiver<LoadResult>),
+}
+
+impl InFlightLoads {
+    fn with_map<R>(&self, f: impl FnOnce(&mut HashMap<InFlightKey, Waiters>) -> R) -> R {
+        f(&mut lock(&self.0))
+    }
+
+    pub(crate) fn join(
+        &self,
+        page_name: &'static str,
+        route_hash: u64,
+        vary_hash: u64,
+    ) -> Coalesced<'_> {
+        let key = (page_name, route_hash, vary_hash);
+        self.with_map(|map| match map.get_mut(&key) {
+            Some(waiters) => {
+                let (sender, receiver) = oneshot::channel();
+                waiters.push(sender);
+                Coalesced::Follower(receiver)
+            }
+            None => {
+                map.insert(key, Vec::new());
+                Coalesced::Leader(LeaderGuard {
+                    loads: self,
+                    key,
+                    completed: false,
+                })
+            }
+        })
+    }
+
+    fn take_waiters(&self, key: &InFlightKey) -> Waiters {
+        self.with_map(|map| map.remove(key)).unwrap_or_default()
+    }
+}
+
+/// Guard held by the request running the load.
+/// If dropped before completion (the request was cancelled), the waiters are
+/// notified by the closing of their channel and run the load themselves.
+pub(crate) struct LeaderGuard<'a> {
+    loads: &'a InFlightLoads,
+    key: InFlightKey,
+    completed: bool,
+}
+
+impl LeaderGuard<'_> {
+    pub fn complete(mut self, result: &LoadResult) {
+        let waiters = self.loads.take_waiters(&self.key);
+        self.completed = true;
+        for waiter in waiters {
+            // the waiter may have been cancelled, nothing to do in that case.
+            let _ = waiter.send(result.clone());
+        }
+    }
+}
+
+impl Drop for LeaderGuard<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.loads.take_waiters(&self.key);
+        }
+    }
+}
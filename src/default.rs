@@ -84,3 +84,51 @@ impl Deref for NotFound {
         &*self.0
     }
 }
+
+struct DefaultErrorPage;
+
+impl Component for DefaultErrorPage {
+    type Props = ErrorPageProps;
+
+    fn render<'a, G: Html>(cx: Scope<'a>, props: ComponentReactiveProps<'a, Self>) -> View<G> {
+        let status = props.status;
+        let message = props.message.clone();
+        view! { cx,
+            h1 {
+                (status)
+            }
+            p {
+                (message)
+            }
+        }
+    }
+}
+
+pub struct ErrorPageComponent(Box<dyn DynComponent>);
+
+impl Default for ErrorPageComponent {
+    fn default() -> Self {
+        Self::new(DefaultErrorPage)
+    }
+}
+
+impl ErrorPageComponent {
+    fn new<T: ErrorPage>(error_page: T) -> Self {
+        let boxed_error_page = Box::new(error_page);
+        Self(boxed_error_page)
+    }
+}
+
+impl<T: ErrorPage> From<T> for ErrorPageComponent {
+    fn from(value: T) -> Self {
+        Self::new(value)
+    }
+}
+
+impl Deref for ErrorPageComponent {
+    type Target = dyn DynComponent;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
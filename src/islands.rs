@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+
+use stonkks_core::islands::Island;
+use stonkks_core::pages::DynComponent;
+
+type BoxedIsland = Box<dyn DynComponent>;
+
+#[derive(Default)]
+pub struct Islands(HashMap<&'static str, BoxedIsland>);
+
+impl Islands {
+    pub fn add_island<T: Island>(&mut self, island: T) {
+        self.0.insert(T::name(), Box::new(island));
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn DynComponent> {
+        self.0.get(name).map(|island| &**island)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'_ dyn DynComponent> {
+        self.0.values().map(|island| &**island)
+    }
+}
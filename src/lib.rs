@@ -1,19 +1,42 @@
 mod api;
 mod app;
+mod cache;
 mod client;
+mod coalesce;
 mod default;
+mod islands;
+mod middleware;
 mod pages;
+mod rate_limit;
 mod server;
 mod utils;
+mod websocket;
 
 pub mod prelude {
     use super::*;
     pub use app::App;
-    pub use client::Client;
-    pub use server::{Server, ServerResponse};
+    pub use client::{Client, ClientSocket};
+    pub use middleware::{Middleware, MiddlewareRequest, Next, RequestKind, RouteGroup};
+    pub use rate_limit::RateLimit;
+    pub use server::{ErrorBody, ErrorResponse, Server, ServerResponse};
     pub use stonkks_core::predule::*;
+    pub use stonkks_macro::{DynPage, Props};
+    pub use websocket::{WebSocketAccept, WebSocketUpgrade};
+}
+
+// not in the prelude, `response::Html` would clash with the `Html` trait of sycamore.
+pub use stonkks_core::response;
+
+// the code generated by `derive(Props)` refers to sycamore through stonkks,
+// so it does not depend on the name of the sycamore dependency of the user.
+pub use sycamore;
+
+// used by the code generated by `derive(DynPage)`.
+#[doc(hidden)]
+pub mod __private {
+    pub use async_trait::async_trait;
+    pub use stonkks_core::loaders::{get_loader_page_props, Loaders};
 }
 
 // TODO:
 // route macro
-// props macro.
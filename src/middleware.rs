@@ -0,0 +1,247 @@
+use std::borrow::Cow;
+use std::net::IpAddr;
+
+use crate::server::{Server, ServerResponse};
+
+use stonkks_core::errors::StonkksError;
+use stonkks_core::predule::*;
+use stonkks_core::request::{ExtractRequest, RequestParts};
+use stonkks_core::states::StatesMap;
+
+/// Kind of request handled by `Server::respond`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKind {
+    Page,
+    /// Props of a page, requested by the client when navigating.
+    Props,
+    Api,
+    /// Upgrade request of a WebSocket route, see `Server::accept_websocket`.
+    WebSocket,
+}
+
+/// Request going through the middlewares.
+pub struct MiddlewareRequest<'a, 'url> {
+    method: Method,
+    kind: RequestKind,
+    url_infos: &'a OwnedUrlInfos<'url>,
+    states: &'a StatesMap,
+    client_ip: Option<IpAddr>,
+    // only cloned when a middleware modifies them.
+    headers: Cow<'a, Headers>,
+    body: &'a RequestBody,
+}
+
+impl<'a, 'url> MiddlewareRequest<'a, 'url> {
+    pub(crate) fn new(
+        method: Method,
+        kind: RequestKind,
+        url_infos: &'a OwnedUrlInfos<'url>,
+        states: &'a StatesMap,
+        client_ip: Option<IpAddr>,
+        headers: &'a Headers,
+        body: &'a RequestBody,
+    ) -> Self {
+        MiddlewareRequest {
+            method,
+            kind,
+            url_infos,
+            states,
+            client_ip,
+            headers: Cow::Borrowed(headers),
+            body,
+        }
+    }
+
+    pub fn method(&self) -> Method {
+        self.method
+    }
+
+    pub fn kind(&self) -> RequestKind {
+        self.kind
+    }
+
+    /// Full url of the request, including the `/api/`, `/props/` or `/ws/` segment.
+    pub fn url_infos(&self) -> &'a OwnedUrlInfos<'url> {
+        self.url_infos
+    }
+
+    /// Url matched by the routes, without the `/api/`, `/props/` or `/ws/` segment.
+    pub fn route_url(&self) -> UrlInfos<'a, 'url> {
+        match self.kind {
+            RequestKind::Page => self.url_infos.to_shared(),
+            RequestKind::Props | RequestKind::Api | RequestKind::WebSocket => self
+                .url_infos
+                .to_shared_shifted()
+                .map(|(_, url_infos)| url_infos)
+                .unwrap_or_else(|| self.url_infos.to_shared()),
+        }
+    }
+
+    /// Address of the client, when given by the adapter to `Server::respond_from`.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        self.client_ip
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Headers passed down to the next middlewares and to the page or the api route.
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        self.headers.to_mut()
+    }
+
+    pub fn body(&self) -> &'a RequestBody {
+        self.body
+    }
+
+    /// Extract states and request data, like in `Api::respond`.
+    ///
+    /// ```ignore
+    /// let (State(sessions), cookies) = request.extract::<(State<&Sessions>, Cookies)>()?;
+    /// ```
+    pub fn extract<'r, T: ExtractRequest<'r>>(&'r self) -> Result<T, StonkksError> {
+        T::extract(RequestParts {
+            states: self.states,
+            headers: &self.headers,
+            query: self.url_infos.to_shared().query(),
+        })
+    }
+}
+
+/// Routes a middleware applies to, see `App::middleware_for`.
+///
+/// ```ignore
+/// // pages under /account, and their props.
+/// RouteGroup::pages().prefix("/account")
+/// ```
+#[derive(Debug, Clone)]
+pub struct RouteGroup {
+    pages: bool,
+    api: bool,
+    prefix: Vec<String>,
+}
+
+impl RouteGroup {
+    pub fn all() -> Self {
+        RouteGroup {
+            pages: true,
+            api: true,
+            prefix: Vec::new(),
+        }
+    }
+
+    /// Pages, with the requests of their props.
+    pub fn pages() -> Self {
+        RouteGroup {
+            api: false,
+            ..Self::all()
+        }
+    }
+
+    /// Api routes, with the upgrade requests of the WebSocket routes.
+    pub fn api() -> Self {
+        RouteGroup {
+            pages: false,
+            ..Self::all()
+        }
+    }
+
+    /// Only the routes under the path, the `/api/`, `/props/` and `/ws/` segments are not
+    /// part of it.
+    pub fn prefix(mut self, path: &str) -> Self {
+        self.prefix = path
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(str::to_owned)
+            .collect();
+        self
+    }
+
+    pub fn matches(&self, request: &MiddlewareRequest) -> bool {
+        let kind_matches = match request.kind() {
+            RequestKind::Page | RequestKind::Props => self.pages,
+            RequestKind::Api | RequestKind::WebSocket => self.api,
+        };
+        if !kind_matches {
+            return false;
+        }
+        let route_url = request.route_url();
+        let segments = route_url.segments();
+        segments.len() >= self.prefix.len()
+            && self
+                .prefix
+                .iter()
+                .zip(segments)
+                .all(|(prefix, segment)| prefix == segment)
+    }
+}
+
+/// Hook around the pages, the api routes and the upgrade requests of the WebSocket routes,
+/// registered with `App::middleware`.
+///
+/// A middleware can modify the request, answer it without calling `next`,
+/// or modify the response returned by `next`.
+///
+/// ```ignore
+/// struct RequireToken;
+///
+/// #[async_trait]
+/// impl Middleware for RequireToken {
+///     async fn handle<'a, 'url>(
+///         &self,
+///         request: &mut MiddlewareRequest<'a, 'url>,
+///         next: Next<'_>,
+///     ) -> Option<Result<ServerResponse, StonkksError>> {
+///         if request.headers().get("Authorization").is_none() {
+///             return Some(Ok(ServerResponse::Api(Response::empty(Status::UNAUTHORIZED))));
+///         }
+///         next.run(request).await
+///     }
+/// }
+/// ```
+#[async_trait::async_trait]
+pub trait Middleware: Send + Sync + 'static {
+    async fn handle<'a, 'url>(
+        &self,
+        request: &mut MiddlewareRequest<'a, 'url>,
+        next: Next<'_>,
+    ) -> Option<Result<ServerResponse, StonkksError>>;
+}
+
+#[derive(Default)]
+pub struct Middlewares(Vec<(RouteGroup, Box<dyn Middleware>)>);
+
+impl Middlewares {
+    pub fn add<T: Middleware>(&mut self, group: RouteGroup, middleware: T) {
+        self.0.push((group, Box::new(middleware)));
+    }
+
+    pub(crate) fn chain<'a>(&'a self, server: &'a Server) -> Next<'a> {
+        Next {
+            server,
+            middlewares: &self.0,
+        }
+    }
+}
+
+/// The remaining middlewares, followed by the page or the api route.
+pub struct Next<'a> {
+    server: &'a Server,
+    middlewares: &'a [(RouteGroup, Box<dyn Middleware>)],
+}
+
+impl<'a> Next<'a> {
+    pub async fn run<'r, 'url>(
+        mut self,
+        request: &mut MiddlewareRequest<'r, 'url>,
+    ) -> Option<Result<ServerResponse, StonkksError>> {
+        while let Some(((group, middleware), rest)) = self.middlewares.split_first() {
+            self.middlewares = rest;
+            if group.matches(request) {
+                return middleware.handle(request, self).await;
+            }
+        }
+        self.server.dispatch(request).await
+    }
+}
@@ -1,10 +1,9 @@
-use crate::utils::{DynPageAndRoute, PageAndProps, StaticPageAndRoute};
+use crate::utils::{DynPageAndRoute, StaticPageAndRoute};
 
 use super::prelude::*;
 use std::hash::Hash;
 use stonkks_core::pages::{DynBasePage, DynPageDyn, DynStaticPage, StaticPage};
 use stonkks_core::routes::UrlInfos;
-use stonkks_core::states::StatesMap;
 
 type BoxedDynPage = Box<dyn DynPageDyn>;
 type BoxedStaticPage = Box<dyn DynStaticPage>;
@@ -22,16 +21,6 @@ impl DynPages {
             .find_map(|page| DynPageAndRoute::try_match_route(&**page, url_infos))
     }
 
-    pub(crate) async fn find_dyn_page_and_props<'a, 'url>(
-        &self,
-        url_infos: UrlInfos<'a, 'url>,
-        states: &StatesMap,
-    ) -> Option<Result<PageAndProps<'_>, String>> {
-        let page_and_route = self.find_dyn_page_and_route(url_infos)?;
-        let result = page_and_route.get_props(states).await;
-        Some(result)
-    }
-
     pub fn add_page<T: DynPage>(&mut self, page: T) {
         self.add_boxed_page(Box::new(page));
     }
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::middleware::{Middleware, MiddlewareRequest, Next};
+use crate::server::ServerResponse;
+
+use stonkks_core::errors::StonkksError;
+
+/// Buckets are pruned when the map grows past this size.
+const MIN_PRUNE_SIZE: usize = 1024;
+
+type KeyFn = Box<dyn Fn(&MiddlewareRequest) -> Option<String> + Send + Sync>;
+
+/// Client a limit applies to.
+enum RateLimitKey {
+    ClientIp,
+    Header(String),
+    Custom(KeyFn),
+}
+
+impl RateLimitKey {
+    fn extract(&self, request: &MiddlewareRequest) -> Option<String> {
+        match self {
+            RateLimitKey::ClientIp => request.client_ip().map(|ip| ip.to_string()),
+            RateLimitKey::Header(name) => request.headers().get(name).map(str::to_owned),
+            RateLimitKey::Custom(key_fn) => key_fn(request),
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+struct Buckets {
+    buckets: HashMap<String, Bucket>,
+    prune_at: usize,
+}
+
+/// Token bucket rate limiter, a middleware answering with a 429 and a `Retry-After`
+/// header once a client used all its requests.
+///
+/// Each client can send `burst` requests at once, then one request every
+/// `period / requests`. Clients are keyed by ip by default, requests without a key,
+/// like the ones given by the adapter to `Server::respond` instead of
+/// `Server::respond_from`, are not limited.
+///
+/// ```ignore
+/// App::new().middleware_for(
+///     RouteGroup::api().prefix("/search"),
+///     RateLimit::per_minute(30).key_by_header("Authorization"),
+/// )
+/// ```
+pub struct RateLimit {
+    burst: u32,
+    interval: Duration,
+    key: RateLimitKey,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimit {
+    /// Allow `requests` requests per `period`, with a burst of `requests`.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        let requests = requests.max(1);
+        RateLimit {
+            burst: requests,
+            interval: period / requests,
+            key: RateLimitKey::ClientIp,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                prune_at: MIN_PRUNE_SIZE,
+            }),
+        }
+    }
+
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(1))
+    }
+
+    pub fn per_minute(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(60))
+    }
+
+    /// Number of requests a client can send at once.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = burst.max(1);
+        self
+    }
+
+    /// Key clients by ip, the adapter must give it to `Server::respond_from`,
+    /// the requests passed to `Server::respond` are not limited.
+    pub fn key_by_ip(mut self) -> Self {
+        self.key = RateLimitKey::ClientIp;
+        self
+    }
+
+    /// Key clients by the value of a header, like an api key.
+    /// Requests without the header are not limited, a middleware requiring it must run first.
+    pub fn key_by_header<T: Into<String>>(mut self, name: T) -> Self {
+        self.key = RateLimitKey::Header(name.into());
+        self
+    }
+
+    /// Key clients with a function, like the user of a session cookie.
+    /// Requests for which it returns `None` are not limited.
+    pub fn key_by<F>(mut self, key_fn: F) -> Self
+    where
+        F: Fn(&MiddlewareRequest) -> Option<String> + Send + Sync + 'static,
+    {
+        self.key = RateLimitKey::Custom(Box::new(key_fn));
+        self
+    }
+
+    /// Take a token from the bucket of the key,
+    /// returns the delay before the next token when it is empty.
+    fn acquire(&self, key: String, now: Instant) -> Result<(), Duration> {
+        let burst = f64::from(self.burst);
+        let interval = self.interval.as_secs_f64();
+        // a panic while holding the lock can't leave the buckets in an invalid state.
+        let mut buckets = self.buckets.lock().unwrap_or_else(|err| err.into_inner());
+        if buckets.buckets.len() >= buckets.prune_at {
+            buckets.prune(now, burst, interval);
+        }
+        let bucket = buckets.buckets.entry(key).or_insert(Bucket {
+            tokens: burst,
+            last_refill: now,
+        });
+        let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+        bucket.tokens = if interval > 0.0 {
+            (bucket.tokens + elapsed / interval).min(burst)
+        } else {
+            burst
+        };
+        bucket.last_refill = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) * interval))
+        }
+    }
+}
+
+impl Buckets {
+    /// Remove the buckets refilled since their last request, they are the same as new ones.
+    fn prune(&mut self, now: Instant, burst: f64, interval: f64) {
+        self.buckets.retain(|_, bucket| {
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens + elapsed / interval < burst
+        });
+        self.prune_at = (self.buckets.len() * 2).max(MIN_PRUNE_SIZE);
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for RateLimit {
+    async fn handle<'a, 'url>(
+        &self,
+        request: &mut MiddlewareRequest<'a, 'url>,
+        next: Next<'_>,
+    ) -> Option<Result<ServerResponse, StonkksError>> {
+        // sharing a bucket between all the requests without a key would let
+        // a single client lock out the others.
+        let Some(key) = self.key.extract(request) else {
+            return next.run(request).await;
+        };
+        if let Err(retry_after) = self.acquire(key, Instant::now()) {
+            // `Retry-After` is in whole seconds, round up so the retry is not rejected.
+            let retry_after = Duration::from_secs(retry_after.as_secs_f64().ceil() as u64);
+            return Some(Err(StonkksError::TooManyRequests { retry_after }));
+        }
+        next.run(request).await
+    }
+}
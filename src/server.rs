@@ -1,35 +1,99 @@
 use crate::api::ApiRoutes;
-use crate::app::{default_html_view, AppInner, StaticGenerationError, ROOT_ELEMENT_ID};
+use crate::app::AppInner;
+use crate::cache::{CacheKey, PageCache};
+use crate::coalesce::{Coalesced, InFlightLoads, SharedLoad};
+use crate::middleware::{MiddlewareRequest, Middlewares, RequestKind};
 use crate::pages::StaticPages;
-use crate::utils::PageAndProps;
+use crate::utils::{DynPageAndRoute, PageAndProps};
+use crate::websocket::{WebSocketAccept, WebSocketRoutes, WebSocketUpgrade};
 
 use super::pages::DynPages;
 use super::prelude::*;
-use stonkks_core::layout::DynLayout;
-use stonkks_core::pages::{DynComponent, DynRenderResult};
+use stonkks_core::errors::StonkksError;
+use stonkks_core::pages::DynComponent;
+use stonkks_core::request::RequestParts;
 use stonkks_core::response::Response;
 use stonkks_core::routes::UrlInfos;
 use stonkks_core::states::StatesMap;
 
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
 const API_ROUTE_SEGMENT: &str = "api";
 const STATIC_FILES_ROUTE_SEGMENT: &str = "public";
-const PROPS_ROUTE_SEGMENT: &str = "props";
+pub(crate) const WS_ROUTE_SEGMENT: &str = "ws";
+pub(crate) const PROPS_ROUTE_SEGMENT: &str = "props";
 
 pub struct Server {
     inner: AppInner,
     states: StatesMap,
     api: ApiRoutes,
+    websockets: WebSocketRoutes,
+    middlewares: Middlewares,
+    cache: PageCache,
+    in_flight: InFlightLoads,
 }
 
 pub enum ServerResponse {
-    Props(String),
+    /// Serialized props, with the codec used to serialize them.
+    Props(String, PropsCodec),
     Html(String),
+    /// Response of an api route, or any response built by a middleware.
     Api(Response),
 }
 
+impl ServerResponse {
+    /// The response with its content type, pages and props are turned into a `Response`.
+    pub fn into_response(self) -> Response {
+        match self {
+            ServerResponse::Props(props, codec) => {
+                Response::new(ContentType::new(codec.content_type()), props)
+            }
+            ServerResponse::Html(html) => Response::new(ContentType::HTML, html),
+            ServerResponse::Api(response) => response,
+        }
+    }
+
+    /// Add a header to the response, used by middlewares.
+    pub fn with_header<N: Into<String>, V: Into<String>>(self, name: N, value: V) -> Self {
+        ServerResponse::Api(self.into_response().with_header(name, value))
+    }
+}
+
+/// Body sent back for a failed request, see `Server::error_response`.
+pub enum ErrorBody {
+    /// Error page, for page requests.
+    Html(String),
+    /// JSON body, for props, api and WebSocket requests.
+    Json(String),
+}
+
+pub struct ErrorResponse {
+    pub status: u16,
+    pub body: ErrorBody,
+    /// Headers to add to the response, like the `Allow` header of a 405.
+    pub headers: Vec<(&'static str, String)>,
+}
+
 impl Server {
-    pub fn new(inner: AppInner, api: ApiRoutes, states: StatesMap) -> Self {
-        Server { inner, api, states }
+    pub fn new(
+        inner: AppInner,
+        api: ApiRoutes,
+        websockets: WebSocketRoutes,
+        middlewares: Middlewares,
+        states: StatesMap,
+        cache: PageCache,
+    ) -> Self {
+        Server {
+            inner,
+            api,
+            websockets,
+            middlewares,
+            states,
+            cache,
+            in_flight: InFlightLoads::default(),
+        }
     }
 
     fn dyn_pages(&self) -> &DynPages {
@@ -44,145 +108,461 @@ impl Server {
         self.inner.not_found_page()
     }
 
-    fn layout(&self) -> &dyn DynLayout {
-        self.inner.layout()
+    fn props_codec(&self) -> PropsCodec {
+        self.inner.props_codec()
+    }
+
+    /// Version of the app, see `App::version`.
+    pub fn version(&self) -> &str {
+        self.inner.version()
+    }
+
+    fn cache_key(
+        page_and_route: &DynPageAndRoute,
+        url_infos: UrlInfos,
+        headers: &Headers,
+    ) -> Option<(CacheKey, Duration)> {
+        let policy = page_and_route.cache_policy()?;
+        let page_name = page_and_route.page_name();
+        let route_hash = page_and_route.hash_route();
+        let key = CacheKey::new(page_name, route_hash, &policy, url_infos, headers);
+        Some((key, policy.ttl()))
+    }
+
+    fn request_parts<'r>(
+        &'r self,
+        url_infos: UrlInfos<'_, 'r>,
+        headers: &'r Headers,
+    ) -> RequestParts<'r> {
+        RequestParts {
+            states: &self.states,
+            headers,
+            query: url_infos.query(),
+        }
+    }
+
+    /// Load the props of the page, the html is rendered from them when needed.
+    async fn load_dyn_page(
+        &self,
+        page_and_route: DynPageAndRoute<'_, '_>,
+        parts: RequestParts<'_>,
+    ) -> Result<Arc<SharedLoad>, StonkksError> {
+        let page_and_props = page_and_route.get_props(parts, self.props_codec()).await?;
+        let serialized_props = page_and_props.serialize_props()?;
+        let load = SharedLoad::new(serialized_props, page_and_props.into_props());
+        Ok(Arc::new(load))
+    }
+
+    /// Same as `load_dyn_page`, concurrent requests of a page coalescing them share a single load.
+    /// The props rebuilt from their serialized form would miss their `ServerOnly` values,
+    /// so the requests share the loaded props, and the html rendered from them.
+    /// Only the requests with the same cache key, if the page has a cache policy, are coalesced.
+    async fn load_dyn_page_coalesced(
+        &self,
+        page_and_route: DynPageAndRoute<'_, '_>,
+        cache_key: Option<&CacheKey>,
+        parts: RequestParts<'_>,
+    ) -> Result<Arc<SharedLoad>, StonkksError> {
+        if !page_and_route.coalesce_requests() {
+            return self.load_dyn_page(page_and_route, parts).await;
+        }
+        let page_name = page_and_route.page_name();
+        let route_hash = page_and_route.hash_route();
+        let vary_hash = cache_key.map(CacheKey::vary_hash).unwrap_or_default();
+        match self.in_flight.join(page_name, route_hash, vary_hash) {
+            Coalesced::Leader(guard) => {
+                let result = self.load_dyn_page(page_and_route, parts).await;
+                guard.complete(&result);
+                result
+            }
+            Coalesced::Follower(receiver) => match receiver.await {
+                Ok(result) => result,
+                // the leading request was cancelled.
+                Err(_) => self.load_dyn_page(page_and_route, parts).await,
+            },
+        }
     }
 
-    async fn try_find_dyn_page_and_props<'a, 'url>(
+    async fn try_render_dyn_page<'a, 'url>(
         &self,
         url_infos: UrlInfos<'a, 'url>,
-    ) -> Option<Result<PageAndProps<'_>, String>> {
-        self.dyn_pages()
-            .find_dyn_page_and_props(url_infos, &self.states)
-            .await
+        headers: &Headers,
+    ) -> Option<Result<String, StonkksError>> {
+        let page_and_route = self.dyn_pages().find_dyn_page_and_route(url_infos)?;
+        let cache_key = Self::cache_key(&page_and_route, url_infos, headers);
+        if let Some((key, _)) = &cache_key {
+            if let Some(html) = self.cache.get_html(key) {
+                return Some(Ok(html));
+            }
+        }
+        let page = page_and_route.as_dyn_component();
+        let parts = self.request_parts(url_infos, headers);
+        let load = self.load_dyn_page_coalesced(
+            page_and_route,
+            cache_key.as_ref().map(|(key, _)| key),
+            parts,
+        );
+        let load = match load.await {
+            Ok(load) => load,
+            Err(err) => return Some(Err(err)),
+        };
+        let html = load.html(|props| {
+            let page_and_props = PageAndProps::new(page, props, self.props_codec());
+            self.inner
+                .render_to_string(page_and_props, &load.serialized_props)
+        });
+        let Some(html) = html else {
+            let message = "the render of the props shared with this request panicked";
+            return Some(Err(StonkksError::Render(message.to_owned())));
+        };
+        if let Some((key, ttl)) = cache_key {
+            self.cache
+                .insert(key, ttl, load.serialized_props.clone(), Some(html.clone()));
+        }
+        Some(Ok(html))
+    }
+
+    async fn try_find_dyn_page_props<'a, 'url>(
+        &self,
+        url_infos: UrlInfos<'a, 'url>,
+        headers: &Headers,
+    ) -> Option<Result<(String, PropsCodec), StonkksError>> {
+        let page_and_route = self.dyn_pages().find_dyn_page_and_route(url_infos)?;
+        let codec = page_and_route.props_codec(self.props_codec());
+        let cache_key = Self::cache_key(&page_and_route, url_infos, headers);
+        if let Some((key, _)) = &cache_key {
+            if let Some(props) = self.cache.get_props(key) {
+                return Some(Ok((props, codec)));
+            }
+        }
+        let parts = self.request_parts(url_infos, headers);
+        let load = self.load_dyn_page_coalesced(
+            page_and_route,
+            cache_key.as_ref().map(|(key, _)| key),
+            parts,
+        );
+        let serialized_props = match load.await {
+            Ok(load) => load.serialized_props.clone(),
+            Err(err) => return Some(Err(err)),
+        };
+        if let Some((key, ttl)) = cache_key {
+            self.cache.insert(key, ttl, serialized_props.clone(), None);
+        }
+        Some(Ok((serialized_props, codec)))
     }
 
     async fn try_find_static_page_html<'a, 'url>(
         &self,
         url_infos: UrlInfos<'a, 'url>,
-    ) -> Option<Result<String, String>> {
+    ) -> Option<Result<String, StonkksError>> {
         let page = self.static_pages().find_static_page(url_infos)?;
         let route_hash = page.hash_route();
         let page_name = page.page_name();
         let result = AppInner::get_static_page_html(page_name, route_hash).await;
         match result {
             Ok(html) => Some(Ok(html)),
-            Err(err) => Some(Err(format!("{:?}", err))),
+            Err(err) => Some(Err(err.into())),
         }
     }
 
     async fn try_find_static_page_props<'a, 'url>(
         &self,
         url_infos: UrlInfos<'a, 'url>,
-    ) -> Option<Result<String, String>> {
+    ) -> Option<Result<(String, PropsCodec), StonkksError>> {
         let page = self.static_pages().find_static_page(url_infos)?;
         let route_hash = page.hash_route();
         let page_name = page.page_name();
-        let result = AppInner::get_static_page_props(page_name, route_hash).await;
+        let codec = page.props_codec(self.props_codec());
+        let result = AppInner::get_static_page_props(page_name, route_hash, codec).await;
         match result {
-            Ok(props) => Some(Ok(props)),
-            Err(err) => Some(Err(format!("{:?}", err))),
+            Ok(props) => Some(Ok((props, codec))),
+            Err(err) => Some(Err(err.into())),
         }
     }
 
+    /// Render the page matching the url, as requested without any header.
+    /// See `Server::respond` to render it for a request.
     pub async fn try_render_to_string<'a, 'url>(
         &self,
         url_infos: UrlInfos<'a, 'url>,
-    ) -> Option<Result<String, String>> {
+    ) -> Option<Result<String, StonkksError>> {
+        self.try_render_page(url_infos, &Headers::new()).await
+    }
+
+    /// The headers are part of the cache key of the pages varying on them,
+    /// see `CachePolicy::vary_header`.
+    async fn try_render_page<'a, 'url>(
+        &self,
+        url_infos: UrlInfos<'a, 'url>,
+        headers: &Headers,
+    ) -> Option<Result<String, StonkksError>> {
         let static_page = self.try_find_static_page_html(url_infos).await;
         if let Some(result) = static_page {
             return Some(result);
         }
-        let result = self.try_find_dyn_page_and_props(url_infos).await?;
-        let page_and_props = match result {
-            Ok(page_and_props) => page_and_props,
-            Err(err) => return Some(Err(err)),
-        };
-        let serialize_result = page_and_props.serialize_props();
-        let serialized_props = match serialize_result {
-            Ok(s) => s,
-            Err(err) => return Some(Err(format!("{:?}", err))),
-        };
-        let html = sycamore::render_to_string(|cx| {
-            let DynRenderResult { body, head } = page_and_props.render_server(cx);
-            let body = self.layout().render_server(cx, body);
-            default_html_view(cx, body, head, &serialized_props, true)
-        });
-        Some(Ok(format!(
-            "<!DOCTYPE html><html id=\"{}\">{}</html>",
-            ROOT_ELEMENT_ID, html
-        )))
+        self.try_render_dyn_page(url_infos, headers).await
     }
 
-    pub fn render_not_found(&self) -> Result<String, String> {
-        let not_found_page_props = NotFoundPageProps::new();
+    pub fn render_not_found(&self) -> Result<String, StonkksError> {
+        let not_found_page_props = NotFoundPageProps::new().to_untyped();
         let not_found_page = self.not_found_page();
-        let serialized_props = not_found_page_props
-            .serialize()
-            .map_err(|err| format!("{:?}", err))?;
-        let html = sycamore::render_to_string(|cx| {
-            let DynRenderResult { body, head } =
-                unsafe { not_found_page.render_server(cx, not_found_page_props.to_untyped()) };
-            let body = self.layout().render_server(cx, body);
-            default_html_view(cx, body, head, &serialized_props, true)
-        });
-        Ok(format!(
-            "<!DOCTYPE html><html id=\"{}\">{}</html>",
-            ROOT_ELEMENT_ID, html
-        ))
+        let page_and_props =
+            PageAndProps::new(not_found_page, not_found_page_props, self.props_codec());
+        let serialized_props = page_and_props.serialize_props()?;
+        Ok(self
+            .inner
+            .render_to_string(page_and_props, &serialized_props))
     }
 
     async fn try_find_props<'a, 'url>(
         &self,
         url_infos: UrlInfos<'a, 'url>,
-    ) -> Option<Result<String, String>> {
+        headers: &Headers,
+    ) -> Option<Result<(String, PropsCodec), StonkksError>> {
         if let Some(result) = self.try_find_static_page_props(url_infos).await {
             return Some(result);
         }
-        let result = self.try_find_dyn_page_and_props(url_infos).await?;
-        let page_and_props = match result {
-            Ok(page_and_props) => page_and_props,
-            Err(err) => return Some(Err(err)),
-        };
-        let serialize_result = page_and_props.serialize_props();
-        Some(serialize_result.map_err(|err| format!("{:?}", err)))
+        self.try_find_dyn_page_props(url_infos, headers).await
+    }
+
+    /// Maximum size of the body of the request, adapters should stop reading the body
+    /// past this limit (reading one more byte lets the server reject it with a 413).
+    pub fn body_limit<'url>(&self, method: Method, url_infos: &OwnedUrlInfos<'url>) -> usize {
+        match url_infos.to_shared_shifted() {
+            Some((API_ROUTE_SEGMENT, url_infos)) => self.api.body_limit(method, url_infos),
+            // pages don't read the body.
+            _ => 0,
+        }
     }
 
+    /// Respond to a request, pages and props are only served for GET requests,
+    /// api routes declare the methods they serve with `Api::methods`.
+    /// The request goes through the middlewares of the app first, see `App::middleware`.
     pub async fn respond<'url>(
         &self,
+        method: Method,
         url_infos: &OwnedUrlInfos<'url>,
-    ) -> Option<Result<ServerResponse, String>> {
+        headers: &Headers,
+        body: &RequestBody,
+    ) -> Option<Result<ServerResponse, StonkksError>> {
+        self.respond_from(None, method, url_infos, headers, body)
+            .await
+    }
+
+    /// Same as `Server::respond`, with the address of the client,
+    /// used by the middlewares like `RateLimit`.
+    pub async fn respond_from<'url>(
+        &self,
+        client_ip: Option<IpAddr>,
+        method: Method,
+        url_infos: &OwnedUrlInfos<'url>,
+        headers: &Headers,
+        body: &RequestBody,
+    ) -> Option<Result<ServerResponse, StonkksError>> {
+        let kind = match url_infos.to_shared_shifted() {
+            Some((API_ROUTE_SEGMENT, _)) => RequestKind::Api,
+            _ if method != Method::Get => return None,
+            Some((PROPS_ROUTE_SEGMENT, _)) => RequestKind::Props,
+            Some((STATIC_FILES_ROUTE_SEGMENT, _)) => return None, // static file
+            _ => RequestKind::Page,
+        };
+        let cors = self.api.cors().filter(|_| kind == RequestKind::Api);
+        if let Some(cors) = cors {
+            if Cors::is_preflight(method, headers) {
+                return Some(Ok(ServerResponse::Api(cors.preflight(headers))));
+            }
+        }
+        let mut request = MiddlewareRequest::new(
+            method,
+            kind,
+            url_infos,
+            &self.states,
+            client_ip,
+            headers,
+            body,
+        );
+        let result = self.middlewares.chain(self).run(&mut request).await;
+        match (cors, result) {
+            (Some(cors), Some(Ok(response))) => {
+                let response = cors
+                    .response_headers(headers)
+                    .into_iter()
+                    .fold(response, |response, (name, value)| {
+                        response.with_header(name, value)
+                    });
+                Some(Ok(response))
+            }
+            (_, result) => result,
+        }
+    }
+
+    /// Respond to the request once it went through the middlewares.
+    pub(crate) async fn dispatch<'url>(
+        &self,
+        request: &MiddlewareRequest<'_, 'url>,
+    ) -> Option<Result<ServerResponse, StonkksError>> {
+        let url_infos = request.route_url();
+        match request.kind() {
+            RequestKind::Api => self
+                .api
+                .find_and_respond(
+                    request.method(),
+                    url_infos,
+                    &self.states,
+                    request.headers(),
+                    request.body(),
+                )
+                .await
+                .transpose()
+                .map(|response| response.map(ServerResponse::Api))
+                .transpose(),
+            RequestKind::Props => self
+                .try_find_props(url_infos, request.headers())
+                .await
+                .transpose()
+                .map(|props| props.map(|(props, codec)| ServerResponse::Props(props, codec)))
+                .transpose(),
+            RequestKind::Page => self
+                .try_render_page(url_infos, request.headers())
+                .await
+                .transpose()
+                .map(|html| html.map(ServerResponse::Html))
+                .transpose(),
+            // the connection is upgraded by `Server::accept_websocket`.
+            RequestKind::WebSocket => self.websockets.has_route(url_infos).then(|| {
+                Ok(ServerResponse::Api(Response::empty(
+                    Status::SWITCHING_PROTOCOLS,
+                )))
+            }),
+        }
+    }
+
+    /// Whether the url is a WebSocket route, adapters should pass the upgrade requests
+    /// to `Server::accept_websocket`.
+    pub fn is_websocket<'url>(&self, url_infos: &OwnedUrlInfos<'url>) -> bool {
         match url_infos.to_shared_shifted() {
-            Some((PROPS_ROUTE_SEGMENT, url_infos)) => {
-                // props API
-                self.try_find_props(url_infos)
-                    .await
-                    .transpose()
-                    .map(|props| props.map(ServerResponse::Props))
-                    .transpose()
+            Some((WS_ROUTE_SEGMENT, url_infos)) => self.websockets.has_route(url_infos),
+            _ => false,
+        }
+    }
+
+    /// Run an upgrade request through the middlewares, before the connection is upgraded.
+    /// `headers` are the ones of the upgrade request, the handshake itself is up to the
+    /// adapter.
+    ///
+    /// ```ignore
+    /// match server.accept_websocket(client_ip, &url_infos, &headers).await {
+    ///     Some(Ok(WebSocketAccept::Upgrade(upgrade))) => {
+    ///         // a missing state fails the handshake.
+    ///         let connect = upgrade.connect()?;
+    ///         // send the 101 response with `upgrade.response_headers()`, then:
+    ///         connect(socket).await;
+    ///     }
+    ///     Some(Ok(WebSocketAccept::Respond(response))) => { /* rejected by a middleware */ }
+    ///     Some(Err(err)) => { /* see `Server::error_response` */ }
+    ///     None => { /* not a WebSocket route */ }
+    /// }
+    /// ```
+    pub async fn accept_websocket<'a, 'url>(
+        &'a self,
+        client_ip: Option<IpAddr>,
+        url_infos: &'a OwnedUrlInfos<'url>,
+        headers: &Headers,
+    ) -> Option<Result<WebSocketAccept<'a, 'url>, StonkksError>> {
+        let route_url = match url_infos.to_shared_shifted() {
+            Some((WS_ROUTE_SEGMENT, route_url)) if self.websockets.has_route(route_url) => {
+                route_url
             }
-            Some((STATIC_FILES_ROUTE_SEGMENT, _)) => None, // static file
-            Some((API_ROUTE_SEGMENT, url_infos)) => {
-                // api route
-                self.api
-                    .find_and_respond(url_infos, &self.states)
-                    .await
-                    .transpose()
-                    .map(|response| response.map(ServerResponse::Api))
-                    .transpose()
+            _ => return None,
+        };
+        let body = RequestBody::empty();
+        let mut request = MiddlewareRequest::new(
+            Method::Get,
+            RequestKind::WebSocket,
+            url_infos,
+            &self.states,
+            client_ip,
+            headers,
+            &body,
+        );
+        let accept = match self.middlewares.chain(self).run(&mut request).await? {
+            Ok(ServerResponse::Api(response)) if response.status == Status::SWITCHING_PROTOCOLS => {
+                WebSocketAccept::Upgrade(WebSocketUpgrade {
+                    routes: &self.websockets,
+                    states: &self.states,
+                    route_url,
+                    headers: request.headers().clone(),
+                    response_headers: response.headers,
+                })
             }
-            _ => {
-                // possible page
-                self.try_render_to_string(url_infos.to_shared())
-                    .await
-                    .transpose()
-                    .map(|html| html.map(ServerResponse::Html))
-                    .transpose()
+            Ok(response) => WebSocketAccept::Respond(response),
+            Err(err) => return Some(Err(err)),
+        };
+        Some(Ok(accept))
+    }
+
+    /// Build the response for a request that failed with the given error.
+    /// Page requests get the error page, props, api and WebSocket requests get a JSON body.
+    /// `headers` are the ones of the request, used for the CORS headers of api routes.
+    pub fn error_response<'url>(
+        &self,
+        url_infos: &OwnedUrlInfos<'url>,
+        headers: &Headers,
+        error: &StonkksError,
+    ) -> ErrorResponse {
+        let report = error.report();
+        let segment = url_infos.to_shared_shifted().map(|(segment, _)| segment);
+        let body = match segment {
+            Some(PROPS_ROUTE_SEGMENT | API_ROUTE_SEGMENT | WS_ROUTE_SEGMENT) => {
+                ErrorBody::Json(report.to_json())
             }
+            _ => ErrorBody::Html(self.inner.render_error_page(&report)),
+        };
+        let mut response_headers = Vec::new();
+        match error {
+            StonkksError::MethodNotAllowed(allowed) => {
+                response_headers.push(("Allow", Method::allow_header(allowed)));
+            }
+            StonkksError::TooManyRequests { retry_after } => {
+                response_headers.push(("Retry-After", retry_after.as_secs().to_string()));
+            }
+            _ => (),
+        }
+        // cross-origin clients must be able to read the errors too.
+        if let (Some(API_ROUTE_SEGMENT), Some(cors)) = (segment, self.api.cors()) {
+            response_headers.extend(cors.response_headers(headers));
+        }
+        ErrorResponse {
+            status: report.status,
+            body,
+            headers: response_headers,
         }
     }
 
-    pub async fn generate_static_pages(&self) -> Result<(), StaticGenerationError> {
+    pub async fn generate_static_pages(&self) -> Result<(), StonkksError> {
         self.inner.generate_static_pages(&self.states).await
     }
+
+    /// Remove all the cached responses of the given page.
+    pub fn invalidate_page<T: DynPage>(&self) {
+        self.cache.invalidate_page(std::any::type_name::<T>());
+    }
+
+    /// Remove the cached responses of the page matching the given url.
+    pub fn invalidate_url(&self, url: &str) {
+        let url_infos = OwnedUrlInfos::parse_from_url(url);
+        let page_and_route = self
+            .dyn_pages()
+            .find_dyn_page_and_route(url_infos.to_shared());
+        if let Some(page_and_route) = page_and_route {
+            let page_name = page_and_route.page_name();
+            let route_hash = page_and_route.hash_route();
+            self.cache.invalidate_route(page_name, route_hash);
+        }
+    }
+
+    /// Remove all the cached responses.
+    pub fn clear_cache(&self) {
+        self.cache.clear();
+    }
 }
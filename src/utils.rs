@@ -1,6 +1,12 @@
+use std::sync::Arc;
+
 use stonkks_core::{
-    pages::{DynComponent, DynPageDyn, DynRenderResult, DynStaticPage},
+    cache::CachePolicy,
+    codec::{CodecError, PropsCodec},
+    errors::StonkksError,
+    pages::{DynComponent, DynPageDyn, DynRenderResult, DynStaticPage, RenderMode},
     pointers::{PropsUntypedPtr, RouteUntypedPtr},
+    request::RequestParts,
     routes::UrlInfos,
     states::StatesMap,
 };
@@ -20,18 +26,43 @@ impl<'a, 'url> DynPageAndRoute<'a, 'url> {
         Some(DynPageAndRoute { page, route })
     }
 
-    pub async fn get_props(self, states: &StatesMap) -> Result<PageAndProps<'a>, String> {
-        let props = unsafe { self.page.get_server_props(self.route, states).await? };
-        Ok(PageAndProps {
-            page: self.page.as_dyn_component(),
-            props,
-        })
+    pub async fn get_props(
+        self,
+        parts: RequestParts<'_>,
+        default_codec: PropsCodec,
+    ) -> Result<PageAndProps<'a>, StonkksError> {
+        let props = unsafe { self.page.get_server_props(self.route, parts).await? };
+        let page = self.page.as_dyn_component();
+        Ok(PageAndProps::new(page, props, default_codec))
+    }
+
+    pub fn as_dyn_component(&self) -> &'a dyn DynComponent {
+        self.page.as_dyn_component()
+    }
+
+    pub fn hash_route(&self) -> u64 {
+        unsafe { self.page.hash_route(&self.route) }
+    }
+
+    pub fn page_name(&self) -> &'static str {
+        self.page.get_name()
+    }
+
+    pub fn cache_policy(&self) -> Option<CachePolicy> {
+        self.page.cache_policy()
+    }
+
+    pub fn coalesce_requests(&self) -> bool {
+        self.page.coalesce_requests()
+    }
+
+    pub fn props_codec(&self, default_codec: PropsCodec) -> PropsCodec {
+        self.page.props_codec().unwrap_or(default_codec)
     }
 }
 
 pub(crate) struct StaticPageAndRoute<'a, 'url> {
     page: &'a dyn DynStaticPage,
-    #[allow(unused)]
     route: RouteUntypedPtr<'url>,
 }
 
@@ -44,12 +75,18 @@ impl<'a, 'url> StaticPageAndRoute<'a, 'url> {
         Some(StaticPageAndRoute { page, route })
     }
 
-    pub async fn get_props(self, states: &StatesMap) -> Result<PageAndProps<'a>, String> {
+    pub async fn get_props(
+        self,
+        states: &StatesMap,
+        default_codec: PropsCodec,
+    ) -> Result<PageAndProps<'a>, StonkksError> {
         let props = unsafe { self.page.get_props(self.route, states).await? };
-        Ok(PageAndProps {
-            page: self.page.as_dyn_component(),
-            props,
-        })
+        let page = self.page.as_dyn_component();
+        Ok(PageAndProps::new(page, props, default_codec))
+    }
+
+    pub fn props_codec(&self, default_codec: PropsCodec) -> PropsCodec {
+        self.page.props_codec().unwrap_or(default_codec)
     }
 
     pub fn hash_route(&self) -> u64 {
@@ -61,34 +98,60 @@ impl<'a, 'url> StaticPageAndRoute<'a, 'url> {
     }
 }
 
+/// A page with it's props, and the codec used for the props:
+/// the one of the page if set, the default one of the app otherwise.
 pub(crate) struct PageAndProps<'a> {
     page: &'a dyn DynComponent,
     props: PropsUntypedPtr,
+    codec: PropsCodec,
 }
 
 impl<'a> PageAndProps<'a> {
+    pub fn new(
+        page: &'a dyn DynComponent,
+        props: PropsUntypedPtr,
+        default_codec: PropsCodec,
+    ) -> Self {
+        let codec = page.props_codec().unwrap_or(default_codec);
+        PageAndProps { page, props, codec }
+    }
+
     pub fn deserialize(
         page: &'a dyn DynComponent,
         serialized_props: &str,
-    ) -> Result<Self, serde_json::Error> {
-        let props = page.deserialize_props(serialized_props)?;
-        Ok(PageAndProps { page, props })
+        default_codec: PropsCodec,
+    ) -> Result<Self, CodecError> {
+        let codec = page.props_codec().unwrap_or(default_codec);
+        let props = page.deserialize_props(serialized_props, codec)?;
+        Ok(PageAndProps { page, props, codec })
+    }
+
+    pub fn serialize_props(&self) -> Result<String, StonkksError> {
+        let result = unsafe { self.page.serialize_props(&self.props, self.codec) };
+        result.map_err(|err| StonkksError::PropsSerialization(Arc::new(err)))
+    }
+
+    pub fn props_codec(&self) -> PropsCodec {
+        self.codec
+    }
+
+    pub fn into_props(self) -> PropsUntypedPtr {
+        self.props
     }
 
-    pub fn serialize_props(&self) -> Result<String, String> {
-        let result = unsafe { self.page.serialize_props(&self.props) };
-        result.map_err(|err| format!("{:?}", err))
+    pub fn render_mode(&self) -> RenderMode {
+        self.page.render_mode()
     }
 
-    pub fn render_client(self, cx: Scope) -> DynRenderResult<DomNode> {
+    pub fn render_client<'b>(self, cx: Scope<'b>) -> DynRenderResult<'b, DomNode> {
         unsafe { self.page.render_client(cx, self.props) }
     }
 
-    pub fn render_server(self, cx: Scope) -> DynRenderResult<SsrNode> {
+    pub fn render_server<'b>(self, cx: Scope<'b>) -> DynRenderResult<'b, SsrNode> {
         unsafe { self.page.render_server(cx, self.props) }
     }
 
-    pub fn hydrate(self, cx: Scope) -> DynRenderResult<HydrateNode> {
+    pub fn hydrate<'b>(self, cx: Scope<'b>) -> DynRenderResult<'b, HydrateNode> {
         unsafe { self.page.hydrate(cx, self.props) }
     }
 }
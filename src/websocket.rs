@@ -0,0 +1,92 @@
+use crate::server::ServerResponse;
+
+use stonkks_core::errors::StonkksError;
+use stonkks_core::pointers::*;
+use stonkks_core::predule::*;
+use stonkks_core::request::RequestParts;
+use stonkks_core::routes::UrlInfos;
+use stonkks_core::states::StatesMap;
+use stonkks_core::websocket::{DynWebSocketRoute, WsConnect};
+
+#[derive(Default)]
+pub struct WebSocketRoutes(Vec<Box<dyn DynWebSocketRoute>>);
+
+impl WebSocketRoutes {
+    pub fn add_route<T: WebSocketRoute>(&mut self, route: T) {
+        let route: Box<dyn DynWebSocketRoute> = Box::new(route);
+        self.0.push(route);
+    }
+
+    pub fn find_route<'a, 'url>(
+        &self,
+        url_infos: UrlInfos<'a, 'url>,
+    ) -> Option<(&'_ dyn DynWebSocketRoute, RouteUntypedPtr<'url>)> {
+        for route in &self.0 {
+            if let Some(route_ptr) = route.try_match_route(url_infos) {
+                return Some((&**route, route_ptr));
+            }
+        }
+        None
+    }
+
+    pub fn has_route<'a, 'url>(&self, url_infos: UrlInfos<'a, 'url>) -> bool {
+        self.0
+            .iter()
+            .any(|route| route.try_match_route(url_infos).is_some())
+    }
+
+    pub fn find_and_accept<'a, 'url>(
+        &'a self,
+        url_infos: UrlInfos<'a, 'url>,
+        states: &'a StatesMap,
+        headers: &'a Headers,
+    ) -> Option<Result<WsConnect<'a>, StonkksError>> {
+        let (route, route_ptr) = self.find_route(url_infos)?;
+        let parts = RequestParts {
+            states,
+            headers,
+            query: url_infos.query(),
+        };
+        let result = unsafe { route.accept(route_ptr, parts) };
+        Some(result)
+    }
+}
+
+/// Outcome of an upgrade request that went through the middlewares,
+/// see `Server::accept_websocket`.
+pub enum WebSocketAccept<'a, 'url> {
+    /// The connection can be upgraded.
+    Upgrade(WebSocketUpgrade<'a, 'url>),
+    /// A middleware answered the request, the connection must not be upgraded.
+    Respond(ServerResponse),
+}
+
+/// Upgrade request accepted by the middlewares.
+pub struct WebSocketUpgrade<'a, 'url> {
+    pub(crate) routes: &'a WebSocketRoutes,
+    pub(crate) states: &'a StatesMap,
+    pub(crate) route_url: UrlInfos<'a, 'url>,
+    pub(crate) headers: Headers,
+    pub(crate) response_headers: Headers,
+}
+
+impl<'a, 'url> WebSocketUpgrade<'a, 'url> {
+    /// Headers of the upgrade request, as modified by the middlewares.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// Headers added by the middlewares, to send with the `101 Switching Protocols` response.
+    pub fn response_headers(&self) -> &Headers {
+        &self.response_headers
+    }
+
+    /// Extract the state of the route, adapters must call it before sending the
+    /// `101 Switching Protocols` response so a missing state fails the handshake,
+    /// then run the returned connection on the upgraded socket.
+    pub fn connect(&self) -> Result<WsConnect<'_>, StonkksError> {
+        self.routes
+            .find_and_accept(self.route_url, self.states, &self.headers)
+            .expect("the route was matched by `Server::accept_websocket`")
+    }
+}
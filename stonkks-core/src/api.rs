@@ -1,73 +1,176 @@
+use std::fmt::Display;
+
+use crate::errors::{ErrorReport, StonkksError, UserError};
 use crate::pointers::*;
 use crate::predule::*;
+use crate::request::{ExtractBody, ExtractRequest, RequestBody, RequestParts};
 use crate::response::IntoResponse;
 use crate::response::Response;
 use crate::routes::DynRoutable;
-use crate::states::ExtractState;
-use crate::states::StatesMap;
-/// For now api routes just respond with a string,
-/// this will change just need to figure out the api.
-/// TODO:
-///  - support for method type (GET, POST, ect..) (only support get rn)
-///  - better return type
-///  - ??
-use std::fmt::Debug;
+
+/// HTTP method of a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    /// Served by the routes serving GET, without the body of the response.
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    /// Used by the CORS preflight requests, see `Cors`.
+    Options,
+}
+
+impl Method {
+    pub const ALL: [Method; 7] = [
+        Method::Get,
+        Method::Head,
+        Method::Post,
+        Method::Put,
+        Method::Patch,
+        Method::Delete,
+        Method::Options,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+            Method::Options => "OPTIONS",
+        }
+    }
+
+    /// Parse a method from its name, case insensitive.
+    pub fn from_name(name: &str) -> Option<Self> {
+        Method::ALL
+            .into_iter()
+            .find(|method| method.as_str().eq_ignore_ascii_case(name))
+    }
+
+    /// Value of the `Allow` header for the given methods.
+    pub fn allow_header(methods: &[Method]) -> String {
+        let names: Vec<_> = methods.iter().map(Method::as_str).collect();
+        names.join(", ")
+    }
+}
+
+impl Display for Method {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
 
 /// Trait use to create an API route.
 #[async_trait::async_trait]
 pub trait Api: Routable {
     /// Error returned by the `respond` function.
-    /// Must implement `Debug`.
-    type Err<'url>: Debug;
-    /// Extractor used to access states of the server.
-    type State<'r>: ExtractState<'r>;
+    /// See `UserError` for how it is turned into a response.
+    type Err<'url>: UserError;
+    /// Extractor used to access states of the server and the data of the request,
+    /// like `(State<&Db>, Headers, Query<Filter>, Cookies)`. See `ExtractRequest`.
+    type State<'r>: ExtractRequest<'r>;
+    /// Extractor used to access the body of the request, see `ExtractBody`.
+    /// A body that can't be extracted is rejected with a 400, 413 or 415 response.
+    type Body<'r>: ExtractBody<'r>;
     type Output<'url>: IntoResponse;
 
-    /// Function executed when the supplied route match the targeted URL.
+    /// Methods served by the route, a request matching the route with another method
+    /// gets a 405 response. Only GET by default.
+    /// HEAD requests are served by the routes serving GET, and OPTIONS can't be served,
+    /// it is reserved to the CORS preflight requests: registering such a route panics.
+    fn methods() -> &'static [Method] {
+        &[Method::Get]
+    }
+
+    /// Maximum size of the request body in bytes, `None` to use the limit of the `App`.
+    fn body_limit() -> Option<usize> {
+        None
+    }
+
+    /// Function executed when the supplied route match the targeted URL,
+    /// `method` is one of `Api::methods`.
     async fn respond<'url, 'r>(
+        method: Method,
         route: Self::Route<'url>,
         state: Self::State<'r>,
+        body: Self::Body<'r>,
     ) -> Result<Self::Output<'url>, Self::Err<'url>>;
 }
 
 /// Internal trait used to implement the `Api` trait in a dynamic dispatch way.
 /// This trait is NOT meant to be implemented by hand,
 /// it is automaticaly implemented for all types implementing the `Api` trait.
+///
+/// # Safety
+///
+/// Implementors must only downcast the route pointers they receive to the route type
+/// they matched in `DynRoutable::try_match_route`.
 #[async_trait::async_trait]
 pub unsafe trait DynApi: DynRoutable {
+    /// Wrapper for the `Api::methods` function.
+    fn methods(&self) -> &'static [Method];
+
+    /// Wrapper for the `Api::body_limit` function.
+    fn body_limit(&self) -> Option<usize>;
+
     /// Wrapper for the `Api::respond` function, marked as unsafe because of the used of the `RouteUntypedPtr`,
     /// the implementation internally trust the `route_ptr` to be of the valid type.
     /// It is therefore to the caller to make sure the data backed by the pointer is of the correct type.
     /// (lifetime should'nt be a problem, the borrow checker should track it normally)
+    ///
+    /// # Safety
+    ///
+    /// `route_ptr` must have been returned by `try_match_route` on the same route.
     async unsafe fn respond<'url, 'r>(
         &self,
+        method: Method,
         route_ptr: RouteUntypedPtr<'url>,
-        state: &'r StatesMap,
-    ) -> Result<Response, String>;
+        parts: RequestParts<'r>,
+        body: &'r RequestBody,
+        default_body_limit: usize,
+    ) -> Result<Response, StonkksError>;
 }
 
 #[async_trait::async_trait]
 unsafe impl<T: Api> DynApi for T {
+    fn methods(&self) -> &'static [Method] {
+        <T as Api>::methods()
+    }
+
+    fn body_limit(&self) -> Option<usize> {
+        <T as Api>::body_limit()
+    }
+
     async unsafe fn respond<'url, 'r>(
         &self,
+        method: Method,
         route_ptr: RouteUntypedPtr<'url>,
-        state: &'r StatesMap,
-    ) -> Result<Response, String> {
+        parts: RequestParts<'r>,
+        body: &'r RequestBody,
+        default_body_limit: usize,
+    ) -> Result<Response, StonkksError> {
         // trust the caller to pass down a route_ptr of the valid type.
         let route = route_ptr.downcast::<T>();
-        // extract requested states.
-        let state = state
-            .extract::<T::State<'r>>()
-            // extract return the name of the missing ressource
-            .map_err(|err| format!("Missing state {}.", err))?;
+        // extract requested states and request data.
+        let state = <T::State<'r> as ExtractRequest<'r>>::extract(parts)?;
+        // extract the body, rejections are reported as user errors.
+        let body = body
+            .check_limit(<T as Api>::body_limit().unwrap_or(default_body_limit))
+            .and_then(|_| <T::Body<'r> as ExtractBody<'r>>::extract(body))
+            .map_err(|rejection| StonkksError::User(ErrorReport::from_user_error(&rejection)))?;
         // execute original respond function.
-        <T as Api>::respond(*route, state)
+        <T as Api>::respond(method, *route, state, body)
             .await
-            // if failed return the erreur in a Debug formatted string.
-            .map_err(|err| format!("{:?}", err))?
+            // if failed report the user error.
+            .map_err(|err| StonkksError::User(ErrorReport::from_user_error(&err)))?
             // turn it into a response
             .into_response()
             // return the error in a debug formatted way
-            .map_err(|err| format!("{:?}", err))
+            .map_err(|err| StonkksError::Render(format!("{:?}", err)))
     }
 }
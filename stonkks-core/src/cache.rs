@@ -0,0 +1,70 @@
+use std::time::Duration;
+
+/// Caching policy of a dynamic page.
+///
+/// Cached entries are keyed by the hash of the route of the page, and the values
+/// of the url parameters, headers and cookies listed with `vary_param`, `vary_header`
+/// and `vary_cookie`. A page reading a header or a cookie in `DynPage::State`
+/// (with `Headers` or `Cookies`) must vary on it, or the page rendered for one request
+/// is shared with all the others.
+///
+/// ```ignore
+/// CachePolicy::new(Duration::from_secs(60))
+///     .vary_param("page")
+///     .vary_header("Accept-Language")
+///     .vary_cookie("session")
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CachePolicy {
+    ttl: Duration,
+    vary_params: Vec<&'static str>,
+    vary_headers: Vec<&'static str>,
+    vary_cookies: Vec<&'static str>,
+}
+
+impl CachePolicy {
+    /// Create a new policy, cached entries are kept for at most `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        CachePolicy {
+            ttl,
+            vary_params: Vec::new(),
+            vary_headers: Vec::new(),
+            vary_cookies: Vec::new(),
+        }
+    }
+
+    /// Add an url parameter to the cache key,
+    /// requests with different values for this parameter are cached separately.
+    pub fn vary_param(mut self, name: &'static str) -> Self {
+        self.vary_params.push(name);
+        self
+    }
+
+    /// Add a request header to the cache key, case insensitive.
+    pub fn vary_header(mut self, name: &'static str) -> Self {
+        self.vary_headers.push(name);
+        self
+    }
+
+    /// Add a cookie to the cache key.
+    pub fn vary_cookie(mut self, name: &'static str) -> Self {
+        self.vary_cookies.push(name);
+        self
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn vary_params(&self) -> &[&'static str] {
+        &self.vary_params
+    }
+
+    pub fn vary_headers(&self) -> &[&'static str] {
+        &self.vary_headers
+    }
+
+    pub fn vary_cookies(&self) -> &[&'static str] {
+        &self.vary_cookies
+    }
+}
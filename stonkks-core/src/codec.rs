@@ -0,0 +1,174 @@
+use std::fmt::Display;
+
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Format used to serialize the props, both for the props embedded in the page
+/// and the ones served by the props route.
+///
+/// Binary formats are base64 encoded, so the serialized props are always a string.
+/// The codec is set for the whole app with `App::props_codec`, and can be overriden
+/// per page with `Component::props_codec`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum PropsCodec {
+    #[default]
+    Json,
+    #[cfg(feature = "msgpack")]
+    MessagePack,
+    #[cfg(feature = "postcard")]
+    Postcard,
+}
+
+impl PropsCodec {
+    /// Name of the codec, sent to the client along the props.
+    pub fn name(self) -> &'static str {
+        match self {
+            PropsCodec::Json => "json",
+            #[cfg(feature = "msgpack")]
+            PropsCodec::MessagePack => "msgpack",
+            #[cfg(feature = "postcard")]
+            PropsCodec::Postcard => "postcard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(PropsCodec::Json),
+            #[cfg(feature = "msgpack")]
+            "msgpack" => Some(PropsCodec::MessagePack),
+            #[cfg(feature = "postcard")]
+            "postcard" => Some(PropsCodec::Postcard),
+            _ => None,
+        }
+    }
+
+    /// Content type of the props served by the props route.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            PropsCodec::Json => "application/json",
+            #[cfg(any(feature = "msgpack", feature = "postcard"))]
+            _ => "text/plain",
+        }
+    }
+
+    pub fn encode<T: Serialize>(self, value: &T) -> Result<String, CodecError> {
+        match self {
+            PropsCodec::Json => serde_json::to_string(value).map_err(CodecError::Json),
+            #[cfg(feature = "msgpack")]
+            PropsCodec::MessagePack => rmp_serde::to_vec_named(value)
+                .map(|bytes| base64_encode(&bytes))
+                .map_err(CodecError::MessagePackEncode),
+            #[cfg(feature = "postcard")]
+            PropsCodec::Postcard => postcard::to_allocvec(value)
+                .map(|bytes| base64_encode(&bytes))
+                .map_err(CodecError::Postcard),
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, serialized: &str) -> Result<T, CodecError> {
+        match self {
+            PropsCodec::Json => serde_json::from_str(serialized).map_err(CodecError::Json),
+            #[cfg(feature = "msgpack")]
+            PropsCodec::MessagePack => {
+                let bytes = base64_decode(serialized)?;
+                rmp_serde::from_slice(&bytes).map_err(CodecError::MessagePackDecode)
+            }
+            #[cfg(feature = "postcard")]
+            PropsCodec::Postcard => {
+                let bytes = base64_decode(serialized)?;
+                postcard::from_bytes(&bytes).map_err(CodecError::Postcard)
+            }
+        }
+    }
+}
+
+/// Raw encoding, for the transports supporting binary data like WebSockets.
+impl PropsCodec {
+    /// Whether the format is binary, textual formats are sent as text messages.
+    pub fn is_binary(self) -> bool {
+        !matches!(self, PropsCodec::Json)
+    }
+
+    /// Encode the value without the base64 encoding of the binary formats.
+    pub fn encode_bytes<T: Serialize>(self, value: &T) -> Result<Vec<u8>, CodecError> {
+        match self {
+            PropsCodec::Json => serde_json::to_vec(value).map_err(CodecError::Json),
+            #[cfg(feature = "msgpack")]
+            PropsCodec::MessagePack => {
+                rmp_serde::to_vec_named(value).map_err(CodecError::MessagePackEncode)
+            }
+            #[cfg(feature = "postcard")]
+            PropsCodec::Postcard => postcard::to_allocvec(value).map_err(CodecError::Postcard),
+        }
+    }
+
+    pub fn decode_bytes<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, CodecError> {
+        match self {
+            PropsCodec::Json => serde_json::from_slice(bytes).map_err(CodecError::Json),
+            #[cfg(feature = "msgpack")]
+            PropsCodec::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(CodecError::MessagePackDecode)
+            }
+            #[cfg(feature = "postcard")]
+            PropsCodec::Postcard => postcard::from_bytes(bytes).map_err(CodecError::Postcard),
+        }
+    }
+}
+
+#[cfg(any(feature = "msgpack", feature = "postcard"))]
+fn base64_encode(bytes: &[u8]) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD.encode(bytes)
+}
+
+#[cfg(any(feature = "msgpack", feature = "postcard"))]
+fn base64_decode(serialized: &str) -> Result<Vec<u8>, CodecError> {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD
+        .decode(serialized)
+        .map_err(CodecError::Base64)
+}
+
+#[derive(Debug)]
+pub enum CodecError {
+    Json(serde_json::Error),
+    #[cfg(feature = "msgpack")]
+    MessagePackEncode(rmp_serde::encode::Error),
+    #[cfg(feature = "msgpack")]
+    MessagePackDecode(rmp_serde::decode::Error),
+    #[cfg(feature = "postcard")]
+    Postcard(postcard::Error),
+    #[cfg(any(feature = "msgpack", feature = "postcard"))]
+    Base64(base64::DecodeError),
+}
+
+impl Display for CodecError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CodecError::Json(err) => write!(f, "json: {}", err),
+            #[cfg(feature = "msgpack")]
+            CodecError::MessagePackEncode(err) => write!(f, "msgpack: {}", err),
+            #[cfg(feature = "msgpack")]
+            CodecError::MessagePackDecode(err) => write!(f, "msgpack: {}", err),
+            #[cfg(feature = "postcard")]
+            CodecError::Postcard(err) => write!(f, "postcard: {}", err),
+            #[cfg(any(feature = "msgpack", feature = "postcard"))]
+            CodecError::Base64(err) => write!(f, "base64: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CodecError::Json(err) => Some(err),
+            #[cfg(feature = "msgpack")]
+            CodecError::MessagePackEncode(err) => Some(err),
+            #[cfg(feature = "msgpack")]
+            CodecError::MessagePackDecode(err) => Some(err),
+            #[cfg(feature = "postcard")]
+            CodecError::Postcard(err) => Some(err),
+            #[cfg(any(feature = "msgpack", feature = "postcard"))]
+            CodecError::Base64(err) => Some(err),
+        }
+    }
+}
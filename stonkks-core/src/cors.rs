@@ -0,0 +1,225 @@
+use std::time::Duration;
+
+use crate::api::Method;
+use crate::request::Headers;
+use crate::response::{Response, Status};
+
+/// Cross-Origin Resource Sharing policy of the api routes, see `App::cors`.
+///
+/// No origin is allowed by default.
+///
+/// ```ignore
+/// Cors::new()
+///     .allow_origin("https://partner.example.com")
+///     .allow_headers(["Content-Type", "Authorization"])
+///     .allow_credentials(true)
+///     .max_age(Duration::from_secs(3600))
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cors {
+    /// `None` for any origin.
+    origins: Option<Vec<String>>,
+    methods: Vec<Method>,
+    /// `None` for any header.
+    headers: Option<Vec<String>>,
+    expose_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+    /// Policy allowing the GET, POST, PUT, PATCH and DELETE methods, without any origin.
+    pub fn new() -> Self {
+        Cors {
+            origins: Some(Vec::new()),
+            methods: vec![
+                Method::Get,
+                Method::Post,
+                Method::Put,
+                Method::Patch,
+                Method::Delete,
+            ],
+            headers: Some(Vec::new()),
+            expose_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allow an origin, like `https://example.com` (scheme, host and port).
+    pub fn allow_origin<T: Into<String>>(mut self, origin: T) -> Self {
+        if let Some(origins) = &mut self.origins {
+            origins.push(origin.into());
+        }
+        self
+    }
+
+    pub fn allow_origins<I: IntoIterator<Item = T>, T: Into<String>>(self, origins: I) -> Self {
+        origins
+            .into_iter()
+            .fold(self, |cors, origin| cors.allow_origin(origin))
+    }
+
+    /// Allow every origin. With credentials the origin of the request is sent back
+    /// instead of `*`, which browsers reject for credentialed requests.
+    pub fn allow_any_origin(mut self) -> Self {
+        self.origins = None;
+        self
+    }
+
+    /// Replace the allowed methods.
+    pub fn allow_methods(mut self, methods: &[Method]) -> Self {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    /// Allow request headers besides the CORS-safelisted ones, case insensitive.
+    pub fn allow_headers<I: IntoIterator<Item = T>, T: Into<String>>(mut self, headers: I) -> Self {
+        if let Some(allowed) = &mut self.headers {
+            allowed.extend(headers.into_iter().map(Into::into));
+        }
+        self
+    }
+
+    /// Allow every header requested by the preflight requests.
+    pub fn allow_any_header(mut self) -> Self {
+        self.headers = None;
+        self
+    }
+
+    /// Response headers readable by the scripts of the other origins.
+    pub fn expose_headers<I: IntoIterator<Item = T>, T: Into<String>>(
+        mut self,
+        headers: I,
+    ) -> Self {
+        self.expose_headers
+            .extend(headers.into_iter().map(Into::into));
+        self
+    }
+
+    /// Allow requests with cookies or an `Authorization` header.
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// How long browsers can cache the result of a preflight request.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    fn is_origin_allowed(&self, origin: &str) -> bool {
+        match &self.origins {
+            Some(origins) => origins.iter().any(|allowed| allowed == origin),
+            None => true,
+        }
+    }
+
+    fn is_header_allowed(&self, header: &str) -> bool {
+        match &self.headers {
+            Some(headers) => headers
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(header)),
+            None => true,
+        }
+    }
+
+    /// Whether the request is a preflight request, sent by browsers before
+    /// a cross-origin request that is not a simple one.
+    pub fn is_preflight(method: Method, headers: &Headers) -> bool {
+        method == Method::Options
+            && headers.get("Origin").is_some()
+            && headers.get("Access-Control-Request-Method").is_some()
+    }
+
+    /// Response to a preflight request, a 403 when the origin, the method or one of
+    /// the headers is not allowed.
+    pub fn preflight(&self, headers: &Headers) -> Response {
+        let forbidden = Response::empty(Status::FORBIDDEN);
+        let Some(origin) = headers
+            .get("Origin")
+            .filter(|origin| self.is_origin_allowed(origin))
+        else {
+            return forbidden;
+        };
+        let method = headers
+            .get("Access-Control-Request-Method")
+            .and_then(Method::from_name);
+        if !matches!(method, Some(method) if self.methods.contains(&method)) {
+            return forbidden;
+        }
+        let requested_headers: Vec<&str> = headers
+            .get_all("Access-Control-Request-Headers")
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|header| !header.is_empty())
+            .collect();
+        if !requested_headers
+            .iter()
+            .all(|header| self.is_header_allowed(header))
+        {
+            return forbidden;
+        }
+
+        let mut response = Response::empty(Status::NO_CONTENT);
+        for (name, value) in self.origin_headers(origin) {
+            response.headers.append(name, value);
+        }
+        response.headers.append(
+            "Access-Control-Allow-Methods",
+            Method::allow_header(&self.methods),
+        );
+        if !requested_headers.is_empty() {
+            response
+                .headers
+                .append("Access-Control-Allow-Headers", requested_headers.join(", "));
+        }
+        if let Some(max_age) = self.max_age {
+            response
+                .headers
+                .append("Access-Control-Max-Age", max_age.as_secs().to_string());
+        }
+        response
+    }
+
+    /// Headers to add to the response of a cross-origin request,
+    /// none when the request has no `Origin` header or the origin is not allowed.
+    pub fn response_headers(&self, headers: &Headers) -> Vec<(&'static str, String)> {
+        let Some(origin) = headers
+            .get("Origin")
+            .filter(|origin| self.is_origin_allowed(origin))
+        else {
+            return Vec::new();
+        };
+        let mut response_headers = self.origin_headers(origin);
+        if !self.expose_headers.is_empty() {
+            response_headers.push((
+                "Access-Control-Expose-Headers",
+                self.expose_headers.join(", "),
+            ));
+        }
+        response_headers
+    }
+
+    fn origin_headers(&self, origin: &str) -> Vec<(&'static str, String)> {
+        let mut headers = Vec::new();
+        if self.origins.is_none() && !self.credentials {
+            headers.push(("Access-Control-Allow-Origin", "*".to_owned()));
+        } else {
+            headers.push(("Access-Control-Allow-Origin", origin.to_owned()));
+            // the response depends on the origin, caches must not share it.
+            headers.push(("Vary", "Origin".to_owned()));
+        }
+        if self.credentials {
+            headers.push(("Access-Control-Allow-Credentials", "true".to_owned()));
+        }
+        headers
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
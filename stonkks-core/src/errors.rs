@@ -0,0 +1,222 @@
+use std::fmt::{Debug, Display};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::api::Method;
+use crate::codec::CodecError;
+
+/// Trait implemented by the errors returned by pages and API routes,
+/// describing how the error is turned into an HTTP response.
+pub trait UserError: Debug + Send {
+    /// HTTP status code of the response, 500 by default.
+    fn status(&self) -> u16 {
+        500
+    }
+
+    /// Message sent to the client, the debug representation of the error is never sent.
+    fn public_message(&self) -> String {
+        default_status_message(self.status()).into()
+    }
+
+    /// Whether the error should be logged by the server, by default only server errors are.
+    fn should_log(&self) -> bool {
+        self.status() >= 500
+    }
+}
+
+impl UserError for () {}
+
+impl UserError for &str {}
+
+impl UserError for String {}
+
+pub fn default_status_message(status: u16) -> &'static str {
+    match status {
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        415 => "Unsupported Media Type",
+        422 => "Unprocessable Entity",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ if status < 500 => "Client Error",
+        _ => "Server Error",
+    }
+}
+
+/// Structured error carried from the pages and API routes up to the server response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ErrorReport {
+    /// HTTP status code of the response.
+    pub status: u16,
+    /// Message that can be sent to the client.
+    pub message: String,
+    /// Whether the error should be logged.
+    pub log: bool,
+    /// Debug representation of the error, must not be sent to the client.
+    pub details: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    status: u16,
+    error: &'a str,
+}
+
+impl ErrorReport {
+    pub fn from_user_error<E: UserError + ?Sized>(err: &E) -> Self {
+        ErrorReport {
+            status: err.status(),
+            message: err.public_message(),
+            log: err.should_log(),
+            details: format!("{:?}", err),
+        }
+    }
+
+    /// Internal error of the framework, always a logged 500.
+    pub fn internal<D: Into<String>>(details: D) -> Self {
+        ErrorReport {
+            status: 500,
+            message: default_status_message(500).into(),
+            log: true,
+            details: details.into(),
+        }
+    }
+
+    /// JSON body sent for errors of the API and props routes.
+    pub fn to_json(&self) -> String {
+        let body = ErrorBody {
+            status: self.status,
+            error: &self.message,
+        };
+        // serializing a struct of a number and a string can't fail.
+        serde_json::to_string(&body).unwrap_or_default()
+    }
+}
+
+impl Display for ErrorReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.status, self.message, self.details)
+    }
+}
+
+impl std::error::Error for ErrorReport {}
+
+/// Error returned by the framework, adapters can branch on the kind of the error
+/// and use `StonkksError::report` to build the response.
+#[derive(Debug, Clone)]
+pub enum StonkksError {
+    /// A state requested by a page or an api route was not registered on the `App`.
+    MissingState(&'static str),
+    /// A route returned by `StaticPage::get_build_routes` did not match the page.
+    RouteMismatch(String),
+    /// The props of a page could not be serialized.
+    PropsSerialization(Arc<CodecError>),
+    /// The props of a page could not be deserialized.
+    PropsDeserialization(Arc<CodecError>),
+    /// A page or an api route returned an error.
+    User(ErrorReport),
+    /// Reading or writing the static pages failed.
+    Io(Arc<std::io::Error>),
+    /// The output of an api route could not be turned into a response,
+    /// or a page could not be rendered.
+    Render(String),
+    /// The route exists but does not serve the method of the request,
+    /// holds the methods it serves for the `Allow` header.
+    MethodNotAllowed(Vec<Method>),
+    /// The client was rate limited, holds the delay after which it can retry
+    /// for the `Retry-After` header.
+    TooManyRequests { retry_after: Duration },
+}
+
+impl StonkksError {
+    /// HTTP status code of the response, only user errors can be something else than a 500.
+    pub fn status(&self) -> u16 {
+        match self {
+            StonkksError::User(report) => report.status,
+            StonkksError::MethodNotAllowed(_) => 405,
+            StonkksError::TooManyRequests { .. } => 429,
+            _ => 500,
+        }
+    }
+
+    /// Report used to build the response, the details of internal errors are never
+    /// part of the public message.
+    pub fn report(&self) -> ErrorReport {
+        match self {
+            StonkksError::User(report) => report.clone(),
+            StonkksError::MethodNotAllowed(_) | StonkksError::TooManyRequests { .. } => {
+                ErrorReport {
+                    status: self.status(),
+                    message: default_status_message(self.status()).into(),
+                    log: false,
+                    details: self.to_string(),
+                }
+            }
+            err => ErrorReport::internal(err.to_string()),
+        }
+    }
+}
+
+impl Display for StonkksError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StonkksError::MissingState(state) => write!(f, "Missing state {}.", state),
+            StonkksError::RouteMismatch(route) => {
+                write!(f, "Route {} does not match the page.", route)
+            }
+            StonkksError::PropsSerialization(err) => {
+                write!(f, "Failed to serialize props: {}", err)
+            }
+            StonkksError::PropsDeserialization(err) => {
+                write!(f, "Failed to deserialize props: {}", err)
+            }
+            StonkksError::User(report) => write!(f, "{}", report),
+            StonkksError::Io(err) => write!(f, "IO error: {}", err),
+            StonkksError::Render(details) => write!(f, "Failed to render: {}", details),
+            StonkksError::MethodNotAllowed(allowed) => write!(
+                f,
+                "Method not allowed, the route serves: {}.",
+                Method::allow_header(allowed)
+            ),
+            StonkksError::TooManyRequests { retry_after } => write!(
+                f,
+                "Too many requests, retry in {} seconds.",
+                retry_after.as_secs()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StonkksError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            StonkksError::PropsSerialization(err) | StonkksError::PropsDeserialization(err) => {
+                Some(&**err)
+            }
+            StonkksError::Io(err) => Some(&**err),
+            StonkksError::User(report) => Some(report),
+            _ => None,
+        }
+    }
+}
+
+impl From<ErrorReport> for StonkksError {
+    fn from(value: ErrorReport) -> Self {
+        StonkksError::User(value)
+    }
+}
+
+impl From<std::io::Error> for StonkksError {
+    fn from(value: std::io::Error) -> Self {
+        StonkksError::Io(Arc::new(value))
+    }
+}
@@ -0,0 +1,201 @@
+use sycamore::prelude::*;
+
+/// A single element living in the `<head>` of the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HeadElement {
+    /// `<title>` element.
+    Title(String),
+    /// `<meta name=".." content="..">` element.
+    Meta { name: String, content: String },
+    /// `<meta property=".." content="..">` element, used by Open Graph.
+    Property { property: String, content: String },
+    /// `<link rel=".." href="..">` element.
+    Link { rel: String, href: String },
+    /// `<script src="..">` element.
+    Script { src: String },
+    /// `<script type="application/ld+json">` element, holding serialized JSON-LD.
+    JsonLd(String),
+}
+
+impl HeadElement {
+    /// Key used to deduplicate elements, two elements with the same key can't coexist in a `Head`.
+    /// Elements returning `None` are never deduplicated.
+    pub fn key(&self) -> Option<String> {
+        match self {
+            HeadElement::Title(_) => Some("title".into()),
+            HeadElement::Meta { name, .. } => Some(format!("meta:{}", name)),
+            HeadElement::Property { property, .. } => Some(format!("property:{}", property)),
+            HeadElement::Link { rel, .. } if rel == "canonical" => Some("link:canonical".into()),
+            HeadElement::Link { rel, href } => Some(format!("link:{}:{}", rel, href)),
+            HeadElement::Script { src } => Some(format!("script:{}", src)),
+            HeadElement::JsonLd(_) => None,
+        }
+    }
+
+    pub fn render<G: Html>(self, cx: Scope) -> View<G> {
+        match self {
+            HeadElement::Title(text) => view! { cx,
+                title { (text) }
+            },
+            HeadElement::Meta { name, content } => view! { cx,
+                meta(name=name, content=content)
+            },
+            HeadElement::Property { property, content } => view! { cx,
+                meta(property=property, content=content)
+            },
+            HeadElement::Link { rel, href } => view! { cx,
+                link(rel=rel, href=href)
+            },
+            HeadElement::Script { src } => view! { cx,
+                script(src=src)
+            },
+            HeadElement::JsonLd(json) => {
+                let json = escape_json_for_script(&json);
+                view! { cx,
+                    script(type="application/ld+json", dangerously_set_inner_html=&json)
+                }
+            }
+        }
+    }
+}
+
+/// Escape the characters of a JSON string that could close the surrounding `<script>` tag.
+/// The escaped string is still valid JSON.
+pub fn escape_json_for_script(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    for c in json.chars() {
+        match c {
+            '<' => escaped.push_str("\\u003c"),
+            '>' => escaped.push_str("\\u003e"),
+            '&' => escaped.push_str("\\u0026"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Structured content of the document `<head>`.
+///
+/// Heads from the framework, the layout and the page are merged together,
+/// elements sharing the same key (see `HeadElement::key`) are deduplicated,
+/// the last one pushed winning. This means the page can override the title set by the layout.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Head {
+    elements: Vec<HeadElement>,
+}
+
+impl Head {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Push an element, replacing in place any previous element with the same key.
+    pub fn push(mut self, element: HeadElement) -> Self {
+        let key = element.key();
+        let position = key.and_then(|key| {
+            self.elements
+                .iter()
+                .position(|el| el.key().as_ref() == Some(&key))
+        });
+        match position {
+            Some(index) => self.elements[index] = element,
+            None => self.elements.push(element),
+        }
+        self
+    }
+
+    /// Merge two heads, elements of `other` take precedence over the ones of `self`.
+    pub fn merge(self, other: Head) -> Self {
+        other.elements.into_iter().fold(self, Head::push)
+    }
+
+    pub fn title<T: Into<String>>(self, title: T) -> Self {
+        self.push(HeadElement::Title(title.into()))
+    }
+
+    pub fn meta<N: Into<String>, C: Into<String>>(self, name: N, content: C) -> Self {
+        let name = name.into();
+        let content = content.into();
+        self.push(HeadElement::Meta { name, content })
+    }
+
+    pub fn description<T: Into<String>>(self, description: T) -> Self {
+        self.meta("description", description)
+    }
+
+    pub fn property<P: Into<String>, C: Into<String>>(self, property: P, content: C) -> Self {
+        let property = property.into();
+        let content = content.into();
+        self.push(HeadElement::Property { property, content })
+    }
+
+    /// Add an Open Graph property, the `og:` prefix is added if missing.
+    pub fn open_graph<P: Into<String>, C: Into<String>>(self, property: P, content: C) -> Self {
+        let property = property.into();
+        let property = if property.starts_with("og:") {
+            property
+        } else {
+            format!("og:{}", property)
+        };
+        self.property(property, content)
+    }
+
+    pub fn og_title<T: Into<String>>(self, title: T) -> Self {
+        self.open_graph("title", title)
+    }
+
+    pub fn og_description<T: Into<String>>(self, description: T) -> Self {
+        self.open_graph("description", description)
+    }
+
+    pub fn og_image<T: Into<String>>(self, url: T) -> Self {
+        self.open_graph("image", url)
+    }
+
+    pub fn og_type<T: Into<String>>(self, og_type: T) -> Self {
+        self.open_graph("type", og_type)
+    }
+
+    pub fn link<R: Into<String>, H: Into<String>>(self, rel: R, href: H) -> Self {
+        let rel = rel.into();
+        let href = href.into();
+        self.push(HeadElement::Link { rel, href })
+    }
+
+    pub fn canonical<T: Into<String>>(self, href: T) -> Self {
+        self.link("canonical", href)
+    }
+
+    pub fn stylesheet<T: Into<String>>(self, href: T) -> Self {
+        self.link("stylesheet", href)
+    }
+
+    pub fn script<T: Into<String>>(self, src: T) -> Self {
+        let src = src.into();
+        self.push(HeadElement::Script { src })
+    }
+
+    pub fn json_ld(self, value: serde_json::Value) -> Self {
+        self.push(HeadElement::JsonLd(value.to_string()))
+    }
+
+    pub fn elements(&self) -> &[HeadElement] {
+        &self.elements
+    }
+
+    pub fn get_title(&self) -> Option<&str> {
+        self.elements.iter().find_map(|el| match el {
+            HeadElement::Title(title) => Some(title.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn render<G: Html>(self, cx: Scope) -> View<G> {
+        let views = self
+            .elements
+            .into_iter()
+            .map(|element| element.render(cx))
+            .collect();
+        View::new_fragment(views)
+    }
+}
@@ -0,0 +1,49 @@
+use sycamore::prelude::*;
+
+use crate::codec::PropsCodec;
+use crate::pages::Component;
+use crate::props::IntoProps;
+
+/// Attribute holding the name of the island on the island root element.
+pub const ISLAND_NAME_ATTRIBUTE: &str = "data-stonkks-island";
+/// Attribute holding the serialized props of the island on the island root element.
+pub const ISLAND_PROPS_ATTRIBUTE: &str = "data-stonkks-props";
+
+/// Trait used to mark a component as an island, an interactive part of a page
+/// rendered with `RenderMode::Islands`.
+/// The rest of the page stay static html, only islands are rendered on the client.
+/// Islands must be registered on the `App` so the client can find them by name.
+pub trait Island: Component {
+    /// Name used to identify the island between the server and the client.
+    fn name() -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// Context provided by the server when rendering a page with `RenderMode::Islands`,
+/// holding the props codec of the app.
+pub struct IslandsRender(pub PropsCodec);
+
+/// Render an island inside a page.
+///
+/// When the page is rendered with `RenderMode::Islands` on the server, the island is rendered
+/// in it's own root with it's props serialized on the wrapping element, so the client can render it.
+/// Otherwise the island is rendered as a normal component.
+pub fn island<'a, T: Island, G: Html>(cx: Scope<'a>, props: T::Props) -> View<G> {
+    let islands_render = try_use_context::<IslandsRender>(cx);
+    let app_codec = match islands_render {
+        Some(IslandsRender(codec)) if !G::IS_BROWSER => *codec,
+        _ => return T::render(cx, props.into_reactive_props(cx)),
+    };
+    let codec = T::props_codec().unwrap_or(app_codec);
+    let serialized_props = match T::serialize_props(&props, codec) {
+        Ok(serialized_props) => serialized_props,
+        // can't be rendered on the client, fallback to a static render.
+        Err(_) => return T::render(cx, props.into_reactive_props(cx)),
+    };
+    let name = T::name();
+    let html = sycamore::render_to_string(|cx| T::render(cx, props.into_reactive_props(cx)));
+    view! { cx,
+        div(data-stonkks-island=name, data-stonkks-props=serialized_props, dangerously_set_inner_html=&html)
+    }
+}
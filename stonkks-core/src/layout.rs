@@ -1,5 +1,7 @@
 use sycamore::prelude::*;
 
+use crate::head::Head;
+
 /// Trait used to make a layout component for the apllication.
 /// This component take as props the view generated by the current page and can render
 /// elements around it (for exemple nav bar, header, footer ect) to have a general layout
@@ -7,6 +9,11 @@ use sycamore::prelude::*;
 pub trait Layout: Send + Sync + 'static {
     /// Function used to render the layout.
     fn render<'a, G: Html>(cx: Scope<'a>, page: View<G>) -> View<G>;
+
+    /// Head elements shared by all pages, the head of the page is merged on top of it.
+    fn head() -> Head {
+        Head::new()
+    }
 }
 
 /// Internal trait used to implement the `Layout` trait in a dynamic dispatch way.
@@ -16,6 +23,7 @@ pub trait DynLayout: Send + Sync {
     fn render_client(&self, cx: Scope, page: View<DomNode>) -> View<DomNode>;
     fn render_server(&self, cx: Scope, page: View<SsrNode>) -> View<SsrNode>;
     fn hydrate(&self, cx: Scope, page: View<HydrateNode>) -> View<HydrateNode>;
+    fn head(&self) -> Head;
 }
 
 impl<T: Layout> DynLayout for T {
@@ -30,4 +38,8 @@ impl<T: Layout> DynLayout for T {
     fn hydrate(&self, cx: Scope, page: View<HydrateNode>) -> View<HydrateNode> {
         T::render(cx, page)
     }
+
+    fn head(&self) -> Head {
+        T::head()
+    }
 }
@@ -1,22 +1,47 @@
 pub mod api;
+pub mod cache;
+pub mod codec;
+pub mod cors;
+pub mod errors;
+pub mod head;
+pub mod islands;
 pub mod layout;
+pub mod loaders;
 pub mod pages;
 pub mod pointers;
 pub mod props;
+pub mod request;
 pub mod response;
 pub mod routes;
+pub mod server_only;
 pub mod states;
+pub mod websocket;
 
 pub mod predule {
     use super::*;
-    pub use api::Api;
+    pub use api::{Api, Method};
+    pub use cache::CachePolicy;
+    pub use codec::PropsCodec;
+    pub use cors::Cors;
+    pub use errors::{ErrorReport, StonkksError, UserError};
+    pub use head::Head;
+    pub use islands::{island, Island};
     pub use layout::Layout;
+    pub use loaders::{Loader, LoaderError, LoaderPage};
     pub use pages::{
-        Component, ComponentReactiveProps, DynPage, NotFoundPage, NotFoundPageProps, Page,
-        StaticPage,
+        Component, ComponentReactiveProps, DynPage, ErrorPage, ErrorPageProps, NotFoundPage,
+        NotFoundPageProps, Page, RenderMode, StaticPage,
+    };
+    pub use props::{snapshot_signal, IntoProps, Props, ReactiveProps, SnapshotProps};
+    pub use request::{
+        BodyRejection, Cookies, Form, Headers, Query, RequestBody, RequestRejection,
+    };
+    pub use response::{
+        Attachment, Bytes, ContentType, IntoResponse, Json, KeepAlive, Response, ResponseBody, Sse,
+        SseEvent, Status, Streaming,
     };
-    pub use props::{IntoProps, Props, ReactiveProps};
-    pub use response::{ContentType, IntoResponse, Json, Response};
     pub use routes::{OwnedUrlInfos, Routable, Route, UrlInfos};
+    pub use server_only::ServerOnly;
     pub use states::State;
+    pub use websocket::{WebSocket, WebSocketRoute, WsChannel, WsConnect, WsError, WsMessage};
 }
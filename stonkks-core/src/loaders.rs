@@ -0,0 +1,222 @@
+use std::fmt::Display;
+use std::marker::PhantomData;
+
+use async_trait::async_trait;
+
+use crate::cache::CachePolicy;
+use crate::errors::{StonkksError, UserError};
+use crate::pages::Page;
+use crate::request::{ExtractRequest, RequestParts};
+
+/// Trait used to create a data loader, an independent source of data for a page.
+/// The loaders of a `LoaderPage` are run concurrently by the server.
+#[async_trait]
+pub trait Loader: Send + Sync + 'static {
+    /// Input given to the loader, shared by all the loaders run together.
+    /// Usually some data taken from the route.
+    type Input: ?Sized + Sync + 'static;
+    type Output: Send;
+    /// Error returned by the `load` function.
+    /// A loader failing without fallback fails the page with this error,
+    /// see `UserError` for how it is turned into a response.
+    type Err: UserError + 'static;
+    /// Extractor used to access states of the server and the data of the request.
+    type State<'r>: ExtractRequest<'r>;
+
+    /// Name of the loader, used in the error when it fails.
+    fn name() -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    async fn load<'r>(
+        input: &Self::Input,
+        state: Self::State<'r>,
+    ) -> Result<Self::Output, Self::Err>;
+
+    /// Error policy of the loader,
+    /// return `None` to fail the page (default) or a value to use in place of the output.
+    fn fallback(err: &Self::Err) -> Option<Self::Output> {
+        let _err = err;
+        None
+    }
+}
+
+/// Error returned when a loader failed without fallback,
+/// turned into the same response as the error of the loader.
+#[derive(Debug)]
+pub struct LoaderError {
+    pub loader: &'static str,
+    pub error: Box<dyn UserError>,
+}
+
+impl UserError for LoaderError {
+    fn status(&self) -> u16 {
+        self.error.status()
+    }
+
+    fn public_message(&self) -> String {
+        self.error.public_message()
+    }
+
+    fn should_log(&self) -> bool {
+        self.error.should_log()
+    }
+}
+
+impl Display for LoaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Loader {} failed: {:?}", self.loader, self.error)
+    }
+}
+
+impl std::error::Error for LoaderError {}
+
+async fn run_loader<'r, L: Loader>(
+    input: &L::Input,
+    state: L::State<'r>,
+) -> Result<L::Output, LoaderError> {
+    match L::load(input, state).await {
+        Ok(output) => Ok(output),
+        Err(err) => L::fallback(&err).ok_or_else(|| LoaderError {
+            loader: L::name(),
+            error: Box::new(err),
+        }),
+    }
+}
+
+/// Set of loaders run together, implemented for tuples of loaders sharing the same input.
+#[async_trait]
+pub trait LoaderSet: Sized + 'static {
+    type Input: ?Sized + Sync + 'static;
+    type States<'r>: Send;
+    type Output: Send;
+
+    fn extract<'r>(parts: RequestParts<'r>) -> Result<Self::States<'r>, StonkksError>;
+
+    async fn load_all<'r>(
+        states: Self::States<'r>,
+        input: &Self::Input,
+    ) -> Result<Self::Output, LoaderError>;
+}
+
+/// States of a set of loaders, each loader extracting the states it needs.
+/// Used as the `DynPage::State` of the `LoaderPage`s.
+pub struct Loaders<'r, L: LoaderSet>(L::States<'r>, PhantomData<fn() -> L>);
+
+impl<'r, L: LoaderSet> ExtractRequest<'r> for Loaders<'r, L> {
+    fn extract(parts: RequestParts<'r>) -> Result<Self, StonkksError> {
+        let states = L::extract(parts)?;
+        Ok(Loaders(states, PhantomData))
+    }
+}
+
+impl<'r, L: LoaderSet> Loaders<'r, L> {
+    /// Run all the loaders concurrently.
+    async fn load(self, input: &L::Input) -> Result<L::Output, LoaderError> {
+        L::load_all(self.0, input).await
+    }
+}
+
+/// Dynamic page whose props are built from a set of loaders.
+/// The server runs the loaders concurrently, then gives their outputs to `get_server_props`.
+/// A loader failing without fallback fails the page with a `LoaderError`.
+/// The `DynPage` implementation of the page is generated with `derive(DynPage)`.
+///
+/// ```ignore
+/// #[derive(DynPage)]
+/// struct UserPage;
+///
+/// impl LoaderPage for UserPage {
+///     type Err<'url> = LoaderError;
+///     type Loaders = (UserLoader, PostsLoader);
+///
+///     fn loader_input<'a, 'url>(route: &'a UserRoute<'url>) -> &'a str {
+///         route.id
+///     }
+///
+///     async fn get_server_props<'url>(
+///         _route: UserRoute<'url>,
+///         (user, posts): (User, Vec<Post>),
+///     ) -> Result<UserProps, LoaderError> {
+///         Ok(UserProps { user, posts })
+///     }
+/// }
+/// ```
+#[async_trait]
+pub trait LoaderPage: Page + Sync {
+    /// Error returned by `get_server_props`, the errors of the loaders are converted to it.
+    type Err<'url>: UserError + From<LoaderError>;
+    type Loaders: LoaderSet;
+
+    /// Input given to the loaders.
+    fn loader_input<'a, 'url>(
+        route: &'a Self::Route<'url>,
+    ) -> &'a <Self::Loaders as LoaderSet>::Input;
+
+    async fn get_server_props<'url>(
+        route: Self::Route<'url>,
+        loaded: <Self::Loaders as LoaderSet>::Output,
+    ) -> Result<Self::Props, Self::Err<'url>>;
+
+    /// See `DynPage::cache_policy`.
+    fn cache_policy() -> Option<CachePolicy> {
+        None
+    }
+
+    /// See `DynPage::coalesce_requests`.
+    fn coalesce_requests() -> bool {
+        false
+    }
+}
+
+/// Props of a `LoaderPage`, used by the `DynPage` implementation generated by `derive(DynPage)`.
+#[doc(hidden)]
+pub async fn get_loader_page_props<'url, 'r, T: LoaderPage>(
+    route: T::Route<'url>,
+    loaders: Loaders<'r, T::Loaders>,
+) -> Result<T::Props, T::Err<'url>> {
+    let loaded = loaders.load(T::loader_input(&route)).await?;
+    T::get_server_props(route, loaded).await
+}
+
+mod impl_macro {
+    use super::*;
+
+    // same as the one used for states.
+    macro_rules! tuple_impls {
+        // Stopping criteria (1-ary tuple)
+        ($T:ident) => {
+            tuple_impls!(@impl $T);
+        };
+        // Running criteria (n-ary tuple, with n >= 2)
+        ($T:ident $( $U:ident )+) => {
+            tuple_impls!($( $U )+);
+            tuple_impls!(@impl $T $( $U )+);
+        };
+        // "Private" internal implementation
+        (@impl $( $T:ident )+) => {
+            #[async_trait]
+            impl<I: ?Sized + Sync + 'static, $($T: Loader<Input = I>),+> LoaderSet for ($($T,)+) {
+                type Input = I;
+                type States<'r> = ($($T::State<'r>,)+);
+                type Output = ($($T::Output,)+);
+
+                fn extract<'r>(parts: RequestParts<'r>) -> Result<Self::States<'r>, StonkksError> {
+                    Ok(($(<$T::State<'r> as ExtractRequest<'r>>::extract(parts)?,)+))
+                }
+
+                #[allow(non_snake_case)]
+                async fn load_all<'r>(
+                    states: Self::States<'r>,
+                    input: &Self::Input,
+                ) -> Result<Self::Output, LoaderError> {
+                    let ($($T,)+) = states;
+                    let ($($T,)+) = futures::join!($(run_loader::<$T>(input, $T)),+);
+                    Ok(($($T?,)+))
+                }
+            }
+        }
+    }
+
+    tuple_impls!(A B C D E F G H); // 8 Max
+}
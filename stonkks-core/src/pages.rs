@@ -1,11 +1,14 @@
 use std::collections::hash_map::DefaultHasher;
-use std::fmt::Debug;
 use std::hash::Hash;
 use std::hash::Hasher;
 
 use async_trait::async_trait;
 use sycamore::prelude::*;
 
+use crate::cache::CachePolicy;
+use crate::codec::{CodecError, PropsCodec};
+use crate::errors::{ErrorReport, StonkksError, UserError};
+use crate::request::{ExtractRequest, RequestParts};
 use crate::routes::DynRoutable;
 use crate::states::ExtractState;
 use crate::states::StatesMap;
@@ -18,20 +21,63 @@ use serde_json::Error;
 
 pub type ComponentReactiveProps<'a, T> = <<T as Component>::Props as IntoProps>::ReactiveProps<'a>;
 
+/// How a page is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    /// Rendered on the server then hydrated on the client.
+    #[default]
+    Hydrate,
+    /// Only rendered on the server, no wasm is shipped to the client.
+    ServerOnly,
+    /// The server only send a shell with the props, the page is rendered on the client.
+    ClientOnly,
+    /// Rendered on the server, only the islands of the page are rendered on the client.
+    /// See `islands::Island`.
+    Islands,
+}
+
 pub trait Component: Send + Sync + 'static {
     type Props: Props;
 
     fn render<'a, G: Html>(cx: Scope<'a>, props: ComponentReactiveProps<'a, Self>) -> View<G>;
-    fn serialize_props(props: &Self::Props) -> Result<String, Error> {
-        serde_json::to_string(props)
+    fn serialize_props(props: &Self::Props, codec: PropsCodec) -> Result<String, CodecError> {
+        codec.encode(props)
+    }
+
+    fn deserialize_props(
+        serialized_props: &str,
+        codec: PropsCodec,
+    ) -> Result<Self::Props, CodecError> {
+        codec.decode(serialized_props)
     }
 
-    fn deserialize_props(serialized_props: &str) -> Result<Self::Props, Error> {
-        serde_json::from_str(serialized_props)
+    /// Codec used for the props of the component, `None` to use the codec of the `App`.
+    fn props_codec() -> Option<PropsCodec> {
+        None
     }
-    fn render_head<'a, G: Html>(cx: Scope<'a>, props: &Self::Props) -> View<G> {
+    /// Head elements of the page, merged with the ones of the layout.
+    /// Elements of the page take precedence.
+    /// The head can depend on the reactive props, on the client the document head
+    /// is updated each time the returned signal changes.
+    fn head<'a>(cx: Scope<'a>, props: &ComponentReactiveProps<'a, Self>) -> &'a ReadSignal<Head> {
         let _props = props;
-        view! { cx, }
+        create_signal(cx, Head::new())
+    }
+
+    fn render_mode() -> RenderMode {
+        RenderMode::default()
+    }
+
+    /// Snapshot of the current state of the page, the client saves it in the history state
+    /// when leaving the page and renders the page from it on back navigation.
+    /// `None` by default, usually implemented with `props::snapshot_signal`.
+    fn snapshot_props<'a>(
+        cx: Scope<'a>,
+        props: &ComponentReactiveProps<'a, Self>,
+    ) -> Option<&'a ReadSignal<Self::Props>> {
+        let _props = props;
+        let _cx = cx;
+        None
     }
 }
 
@@ -52,6 +98,12 @@ impl NotFoundPageProps {
     }
 }
 
+impl Default for NotFoundPageProps {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub struct NotFountPageReactiveProps;
 
 impl ReactiveProps<'_> for NotFountPageReactiveProps {
@@ -72,101 +124,274 @@ pub trait NotFoundPage: Component<Props = NotFoundPageProps> {}
 
 impl<T: Component<Props = NotFoundPageProps>> NotFoundPage for T {}
 
+/// Props of the error page, built from the `ErrorReport` of the failed request.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ErrorPageProps {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ErrorPageProps {
+    pub fn new(report: &ErrorReport) -> Self {
+        ErrorPageProps {
+            status: report.status,
+            message: report.message.clone(),
+        }
+    }
+
+    pub fn to_untyped(self) -> PropsUntypedPtr {
+        PropsUntypedPtr::new_error_props(self)
+    }
+
+    pub fn serialize(&self) -> Result<String, Error> {
+        serde_json::to_string(self)
+    }
+}
+
+impl<'a> ReactiveProps<'a> for ErrorPageProps {
+    type Props = ErrorPageProps;
+}
+
+impl IntoProps for ErrorPageProps {
+    type ReactiveProps<'a> = ErrorPageProps;
+
+    fn into_reactive_props<'a>(self, _cx: Scope<'a>) -> Self::ReactiveProps<'a> {
+        self
+    }
+}
+
+impl Props for ErrorPageProps {}
+
+/// Page rendered when a page fails, always rendered on the server only.
+pub trait ErrorPage: Component<Props = ErrorPageProps> {}
+
+impl<T: Component<Props = ErrorPageProps>> ErrorPage for T {}
+
 pub trait Page: Component + Routable {}
 
 impl<T: Component + Routable> Page for T {}
 
-pub struct DynRenderResult<G: Html> {
+/// Serialize the current snapshot of the page props with the given codec.
+pub type DynSnapshot<'a> = Box<dyn Fn(PropsCodec) -> Result<String, CodecError> + 'a>;
+
+pub struct DynRenderResult<'a, G: Html> {
     pub body: View<G>,
-    pub head: View<G>,
+    pub head: &'a ReadSignal<Head>,
+    /// Only set on the client, see `Component::snapshot_props`.
+    pub snapshot: Option<DynSnapshot<'a>>,
 }
 
+/// Internal trait used to render a `Component` in a dynamic dispatch way.
+///
+/// # Safety
+///
+/// The `PropsUntypedPtr` given to the unsafe functions must back the props type of the
+/// component, they are downcast without any check.
 pub trait DynComponent: Send + Sync {
-    unsafe fn render_client(&self, cx: Scope, props: PropsUntypedPtr) -> DynRenderResult<DomNode>;
-    unsafe fn render_server(&self, cx: Scope, props: PropsUntypedPtr) -> DynRenderResult<SsrNode>;
-    unsafe fn hydrate(&self, cx: Scope, props: PropsUntypedPtr) -> DynRenderResult<HydrateNode>;
-
-    unsafe fn serialize_props(&self, props: &PropsUntypedPtr) -> Result<String, Error>;
-    fn deserialize_props(&self, serialized_props: &str) -> Result<PropsUntypedPtr, Error>;
+    /// # Safety
+    ///
+    /// See the trait documentation.
+    unsafe fn render_client<'a>(
+        &self,
+        cx: Scope<'a>,
+        props: PropsUntypedPtr,
+    ) -> DynRenderResult<'a, DomNode>;
+    /// # Safety
+    ///
+    /// See the trait documentation.
+    unsafe fn render_server<'a>(
+        &self,
+        cx: Scope<'a>,
+        props: PropsUntypedPtr,
+    ) -> DynRenderResult<'a, SsrNode>;
+    /// # Safety
+    ///
+    /// See the trait documentation.
+    unsafe fn hydrate<'a>(
+        &self,
+        cx: Scope<'a>,
+        props: PropsUntypedPtr,
+    ) -> DynRenderResult<'a, HydrateNode>;
+
+    /// # Safety
+    ///
+    /// See the trait documentation.
+    unsafe fn serialize_props(
+        &self,
+        props: &PropsUntypedPtr,
+        codec: PropsCodec,
+    ) -> Result<String, CodecError>;
+    fn deserialize_props(
+        &self,
+        serialized_props: &str,
+        codec: PropsCodec,
+    ) -> Result<PropsUntypedPtr, CodecError>;
+    fn props_codec(&self) -> Option<PropsCodec>;
+    /// Name of the props type, used to compute the version of the app.
+    fn props_type_name(&self) -> &'static str;
+
+    fn render_mode(&self) -> RenderMode;
 }
 
 impl<T: Component> DynComponent for T {
-    unsafe fn render_client(
+    unsafe fn render_client<'a>(
         &self,
-        cx: Scope,
+        cx: Scope<'a>,
         props_ptr: PropsUntypedPtr,
-    ) -> DynRenderResult<DomNode> {
+    ) -> DynRenderResult<'a, DomNode> {
         let props = props_ptr.downcast::<T>();
-        let head = <T as Component>::render_head(cx, &props);
         let reactive_props = props.into_reactive_props(cx);
+        let head = <T as Component>::head(cx, &reactive_props);
+        let snapshot = dyn_snapshot::<T>(cx, &reactive_props);
         let body = <T as Component>::render(cx, reactive_props);
-        DynRenderResult { body, head }
+        DynRenderResult {
+            body,
+            head,
+            snapshot,
+        }
     }
 
-    unsafe fn render_server(
+    unsafe fn render_server<'a>(
         &self,
-        cx: Scope,
+        cx: Scope<'a>,
         props_ptr: PropsUntypedPtr,
-    ) -> DynRenderResult<SsrNode> {
+    ) -> DynRenderResult<'a, SsrNode> {
         let props = props_ptr.downcast::<T>();
-        let head = <T as Component>::render_head(cx, &props);
         let reactive_props = props.into_reactive_props(cx);
+        let head = <T as Component>::head(cx, &reactive_props);
         let body = <T as Component>::render(cx, reactive_props);
-        DynRenderResult { body, head }
+        DynRenderResult {
+            body,
+            head,
+            snapshot: None,
+        }
     }
 
-    unsafe fn hydrate(
+    unsafe fn hydrate<'a>(
         &self,
-        cx: Scope,
+        cx: Scope<'a>,
         props_ptr: PropsUntypedPtr,
-    ) -> DynRenderResult<HydrateNode> {
+    ) -> DynRenderResult<'a, HydrateNode> {
         let props = props_ptr.downcast::<T>();
-        let head = <T as Component>::render_head(cx, &props);
         let reactive_props = props.into_reactive_props(cx);
+        let head = <T as Component>::head(cx, &reactive_props);
+        let snapshot = dyn_snapshot::<T>(cx, &reactive_props);
         let body = <T as Component>::render(cx, reactive_props);
-        DynRenderResult { body, head }
+        DynRenderResult {
+            body,
+            head,
+            snapshot,
+        }
     }
 
-    unsafe fn serialize_props(&self, props: &PropsUntypedPtr) -> Result<String, Error> {
+    unsafe fn serialize_props(
+        &self,
+        props: &PropsUntypedPtr,
+        codec: PropsCodec,
+    ) -> Result<String, CodecError> {
         let shared_props = props.downcast_ref::<T>();
-        T::serialize_props(shared_props)
+        T::serialize_props(shared_props, codec)
     }
 
-    fn deserialize_props(&self, serialized_props: &str) -> Result<PropsUntypedPtr, Error> {
-        let props = T::deserialize_props(serialized_props)?;
+    fn deserialize_props(
+        &self,
+        serialized_props: &str,
+        codec: PropsCodec,
+    ) -> Result<PropsUntypedPtr, CodecError> {
+        let props = T::deserialize_props(serialized_props, codec)?;
         let props_ptr = PropsUntypedPtr::new::<T>(props);
         Ok(props_ptr)
     }
+
+    fn props_codec(&self) -> Option<PropsCodec> {
+        T::props_codec()
+    }
+
+    fn props_type_name(&self) -> &'static str {
+        std::any::type_name::<T::Props>()
+    }
+
+    fn render_mode(&self) -> RenderMode {
+        T::render_mode()
+    }
+}
+
+fn dyn_snapshot<'a, T: Component>(
+    cx: Scope<'a>,
+    reactive_props: &ComponentReactiveProps<'a, T>,
+) -> Option<DynSnapshot<'a>> {
+    let snapshot = T::snapshot_props(cx, reactive_props)?;
+    let serialize = move |codec| T::serialize_props(&snapshot.get(), codec);
+    Some(Box::new(serialize))
 }
 
 pub trait DynBasePage: DynComponent + DynRoutable {
     fn as_dyn_component(&self) -> &dyn DynComponent;
+
+    /// # Safety
+    ///
+    /// `route` must have been returned by `try_match_route` on the same page.
+    unsafe fn hash_route<'url>(&self, route: &RouteUntypedPtr<'url>) -> u64;
+
+    fn get_name(&self) -> &'static str;
 }
 
 impl<T: Page> DynBasePage for T {
     fn as_dyn_component(&self) -> &dyn DynComponent {
         self
     }
+
+    unsafe fn hash_route<'url>(&self, route: &RouteUntypedPtr<'url>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let route = route.downcast_ref::<T>();
+        route.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn get_name(&self) -> &'static str {
+        std::any::type_name::<T>()
+    }
 }
 
 #[async_trait]
 pub trait DynPage: Page + Sync {
-    type Err<'url>: Debug;
-    type State<'r>: ExtractState<'r>;
+    /// Error returned by `get_server_props`, see `UserError` for how it is turned into a response.
+    type Err<'url>: UserError;
+    /// States and data of the request, like the headers or the cookies, used to load the props.
+    /// A page reading the headers or the cookies must list them in its `CachePolicy`.
+    type State<'r>: ExtractRequest<'r>;
     async fn get_server_props<'url, 'r>(
         route: Self::Route<'url>,
         states: Self::State<'r>,
     ) -> Result<Self::Props, Self::Err<'url>>;
+
+    /// Opt-in caching of the rendered page on the server, disabled by default.
+    fn cache_policy() -> Option<CachePolicy> {
+        None
+    }
+
+    /// When enabled, concurrent requests for the same route share a single call
+    /// to `get_server_props`, all of them receiving the same serialized props.
+    /// Requests differing by a parameter, header or cookie listed in the `cache_policy`
+    /// are not coalesced together.
+    fn coalesce_requests() -> bool {
+        false
+    }
 }
 
 #[async_trait]
 pub trait DynPageDyn: DynBasePage {
+    /// # Safety
+    ///
+    /// `route_ptr` must have been returned by `try_match_route` on the same page.
     async unsafe fn get_server_props<'url, 'r>(
         &self,
         route_ptr: RouteUntypedPtr<'url>,
-        states: &'r StatesMap,
-    ) -> Result<PropsUntypedPtr, String>;
+        parts: RequestParts<'r>,
+    ) -> Result<PropsUntypedPtr, StonkksError>;
     fn as_dyn_base_page(&self) -> &dyn DynBasePage;
+    fn cache_policy(&self) -> Option<CachePolicy>;
+    fn coalesce_requests(&self) -> bool;
 }
 
 #[async_trait]
@@ -174,28 +399,34 @@ impl<T: DynPage> DynPageDyn for T {
     async unsafe fn get_server_props<'url, 'r>(
         &self,
         route_ptr: RouteUntypedPtr<'url>,
-        states: &'r StatesMap,
-    ) -> Result<PropsUntypedPtr, String> {
+        parts: RequestParts<'r>,
+    ) -> Result<PropsUntypedPtr, StonkksError> {
         let route = route_ptr.downcast::<T>();
-        let state = states
-            .extract::<T::State<'r>>()
-            .map_err(|err| format!("Missing state {}.", err))?;
+        let state = <T::State<'r> as ExtractRequest<'r>>::extract(parts)?;
         let props_result = <T as DynPage>::get_server_props(*route, state).await;
         match props_result {
             Ok(props) => Ok(PropsUntypedPtr::new::<T>(props)),
-            Err(err) => Err(format!("{:?}", err)),
+            Err(err) => Err(ErrorReport::from_user_error(&err).into()),
         }
     }
 
     fn as_dyn_base_page(&self) -> &dyn DynBasePage {
         self
     }
+
+    fn cache_policy(&self) -> Option<CachePolicy> {
+        <T as DynPage>::cache_policy()
+    }
+
+    fn coalesce_requests(&self) -> bool {
+        <T as DynPage>::coalesce_requests()
+    }
 }
 
 #[async_trait]
 pub trait StaticPage: Page {
-    type RouteError: Debug;
-    type PropsError<'url>: Debug;
+    type RouteError: UserError;
+    type PropsError<'url>: UserError;
 
     type RouteState<'r>: ExtractState<'r>;
     type PropsState<'r>: ExtractState<'r>;
@@ -211,19 +442,18 @@ pub trait StaticPage: Page {
 
 #[async_trait]
 pub trait DynStaticPage: DynBasePage {
+    /// # Safety
+    ///
+    /// `route_ptr` must have been returned by `try_match_route` on the same page.
     async unsafe fn get_props<'url, 'r>(
         &self,
         route_ptr: RouteUntypedPtr<'url>,
         states: &'r StatesMap,
-    ) -> Result<PropsUntypedPtr, String>;
+    ) -> Result<PropsUntypedPtr, StonkksError>;
 
-    async fn get_build_routes(&self, states: &StatesMap) -> Result<Vec<String>, String>;
+    async fn get_build_routes(&self, states: &StatesMap) -> Result<Vec<String>, StonkksError>;
 
     fn as_dyn_base_page(&self) -> &dyn DynBasePage;
-
-    unsafe fn hash_route<'url>(&self, route: &RouteUntypedPtr<'url>) -> u64;
-
-    fn get_name(&self) -> &'static str;
 }
 
 #[async_trait]
@@ -232,38 +462,27 @@ impl<T: StaticPage> DynStaticPage for T {
         &self,
         route_ptr: RouteUntypedPtr<'url>,
         states: &'r StatesMap,
-    ) -> Result<PropsUntypedPtr, String> {
+    ) -> Result<PropsUntypedPtr, StonkksError> {
         let route = route_ptr.downcast::<T>();
         let state = states
             .extract::<T::PropsState<'r>>()
-            .map_err(|err| format!("Missing state {}.", err))?;
+            .map_err(StonkksError::MissingState)?;
         let props_result = <T as StaticPage>::get_props(*route, state).await;
         match props_result {
             Ok(props) => Ok(PropsUntypedPtr::new::<T>(props)),
-            Err(err) => Err(format!("{:?}", err)),
+            Err(err) => Err(ErrorReport::from_user_error(&err).into()),
         }
     }
 
-    async fn get_build_routes(&self, states: &'_ StatesMap) -> Result<Vec<String>, String> {
+    async fn get_build_routes(&self, states: &'_ StatesMap) -> Result<Vec<String>, StonkksError> {
         let states = states
             .extract::<T::RouteState<'_>>()
-            .map_err(|err| format!("Missing state {}.", err))?;
+            .map_err(StonkksError::MissingState)?;
         let routes = <T as StaticPage>::get_build_routes(states).await;
-        routes.map_err(|err| format!("{:?}", err))
+        routes.map_err(|err| ErrorReport::from_user_error(&err).into())
     }
 
     fn as_dyn_base_page(&self) -> &dyn DynBasePage {
         self
     }
-
-    unsafe fn hash_route<'url>(&self, route: &RouteUntypedPtr<'url>) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        let route = route.downcast_ref::<T>();
-        route.hash(&mut hasher);
-        hasher.finish()
-    }
-
-    fn get_name(&self) -> &'static str {
-        std::any::type_name::<T>()
-    }
 }
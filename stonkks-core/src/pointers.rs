@@ -1,4 +1,4 @@
-use super::pages::{Component, NotFoundPageProps};
+use super::pages::{Component, ErrorPageProps, NotFoundPageProps};
 use super::routes::Routable;
 
 use std::any::Any;
@@ -53,19 +53,23 @@ impl<'a> RouteUntypedPtr<'a> {
     }
 
     /// Downcast the backed data into the concrete `Route` of a `Routable` type.
-    /// Marked as unsafe because the function does not check if the given type match
-    /// the type of the backed data.
+    ///
+    /// # Safety
+    ///
+    /// The function does not check if the given type match the type of the backed data.
     /// The caller must make sure the type is correct.
     pub unsafe fn downcast<T: Routable>(self) -> Box<T::Route<'a>> {
         self.0.downcast()
     }
 
+    /// # Safety
+    ///
+    /// Same as `RouteUntypedPtr::downcast`.
     pub unsafe fn downcast_ref<T: Routable>(&self) -> &T::Route<'a> {
         self.0.downcast_ref()
     }
 }
 
-///
 pub struct PropsUntypedPtr(Box<dyn Any + Send>);
 
 impl PropsUntypedPtr {
@@ -84,6 +88,14 @@ impl PropsUntypedPtr {
         Self(boxed_props)
     }
 
+    pub fn new_error_props(props: ErrorPageProps) -> Self {
+        let boxed_props = Box::new(props);
+        Self(boxed_props)
+    }
+
+    /// # Safety
+    ///
+    /// `T::Props` must be the type of the backed props, it is not checked.
     pub unsafe fn downcast<T: Component>(self) -> Box<T::Props> {
         // The best way would be to use `Box::downcast_unchecked` but unstable at the moment.
         let ptr = self.into_raw() as *mut T::Props;
@@ -94,6 +106,9 @@ impl PropsUntypedPtr {
         Box::into_raw(self.0)
     }
 
+    /// # Safety
+    ///
+    /// Same as `PropsUntypedPtr::downcast`.
     pub unsafe fn downcast_ref<T: Component>(&self) -> &T::Props {
         // `Box::downcast_ref_unchecked` would be more suited but unstable at the moment.
         let ptr = self.0.as_ref() as *const _ as *const T::Props;
@@ -18,14 +18,28 @@ pub trait IntoProps {
     fn into_reactive_props<'a>(self, cx: Scope<'a>) -> Self::ReactiveProps<'a>;
 }
 
+/// Reverse of `IntoProps`, build the props from the current value of the reactive props.
+/// Used to snapshot the state of a page, see `Component::snapshot_props`.
+pub trait SnapshotProps<'a>: ReactiveProps<'a> {
+    fn snapshot(&self) -> Self::Props;
+}
+
+/// Signal tracking the snapshot of the reactive props,
+/// the usual implementation of `Component::snapshot_props`.
+pub fn snapshot_signal<'a, R>(cx: Scope<'a>, props: &R) -> &'a ReadSignal<R::Props>
+where
+    R: SnapshotProps<'a> + Clone + 'a,
+{
+    let props = props.clone();
+    create_memo(cx, move || props.snapshot())
+}
+
 impl Props for () {}
 
 impl IntoProps for () {
     type ReactiveProps<'a> = ();
 
-    fn into_reactive_props<'a>(self, _cx: Scope<'a>) -> Self::ReactiveProps<'a> {
-        ()
-    }
+    fn into_reactive_props<'a>(self, _cx: Scope<'a>) -> Self::ReactiveProps<'a> {}
 }
 
 impl<'a> ReactiveProps<'a> for () {
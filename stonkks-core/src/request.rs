@@ -0,0 +1,414 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use serde::de::DeserializeOwned;
+
+use crate::errors::{ErrorReport, StonkksError, UserError};
+use crate::response::Json;
+use crate::states::{AnyState, ExtractState, State, StatesMap};
+
+/// Default maximum size of a request body, 1 MiB.
+/// See `App::body_limit` and `Api::body_limit`.
+pub const DEFAULT_BODY_LIMIT: usize = 1024 * 1024;
+
+/// Body of a request, with its content type.
+#[derive(Debug, Default, Clone)]
+pub struct RequestBody {
+    content_type: Option<String>,
+    bytes: Vec<u8>,
+}
+
+impl RequestBody {
+    pub fn new<B: Into<Vec<u8>>>(bytes: B) -> Self {
+        RequestBody {
+            content_type: None,
+            bytes: bytes.into(),
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    pub fn with_content_type<T: Into<String>>(mut self, content_type: T) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    /// Media type of the body, without the parameters (`application/json; charset=utf-8`
+    /// gives `application/json`).
+    pub fn media_type(&self) -> Option<&str> {
+        let content_type = self.content_type.as_deref()?;
+        let media_type = content_type.split(';').next().unwrap_or_default();
+        Some(media_type.trim())
+    }
+
+    pub fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Reject the body if it's bigger than the limit.
+    pub fn check_limit(&self, limit: usize) -> Result<(), BodyRejection> {
+        if self.len() > limit {
+            Err(BodyRejection::TooLarge { limit })
+        } else {
+            Ok(())
+        }
+    }
+
+    fn expect_media_type(&self, expected: &'static str) -> Result<(), BodyRejection> {
+        match self.media_type() {
+            Some(media_type) if media_type.eq_ignore_ascii_case(expected) => Ok(()),
+            _ => Err(BodyRejection::UnsupportedMediaType { expected }),
+        }
+    }
+
+    fn text(&self) -> Result<&str, BodyRejection> {
+        std::str::from_utf8(&self.bytes)
+            .map_err(|err| BodyRejection::Invalid(format!("Request body is not UTF-8: {}", err)))
+    }
+}
+
+/// Reason a request body was rejected, turned into a 400, 413 or 415 response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BodyRejection {
+    /// The body is bigger than the limit of the route.
+    TooLarge { limit: usize },
+    /// The content type of the body is not the one expected by the extractor.
+    UnsupportedMediaType { expected: &'static str },
+    /// The body could not be parsed.
+    Invalid(String),
+}
+
+impl UserError for BodyRejection {
+    fn status(&self) -> u16 {
+        match self {
+            BodyRejection::TooLarge { .. } => 413,
+            BodyRejection::UnsupportedMediaType { .. } => 415,
+            BodyRejection::Invalid(_) => 400,
+        }
+    }
+
+    fn public_message(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for BodyRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BodyRejection::TooLarge { limit } => {
+                write!(f, "Request body exceeds the limit of {} bytes.", limit)
+            }
+            BodyRejection::UnsupportedMediaType { expected } => {
+                write!(f, "Expected a request body of type {}.", expected)
+            }
+            BodyRejection::Invalid(details) => f.write_str(details),
+        }
+    }
+}
+
+impl std::error::Error for BodyRejection {}
+
+/// Extractor used to access the body of the request in `Api::respond`.
+pub trait ExtractBody<'r>: Sized + Send {
+    fn extract(body: &'r RequestBody) -> Result<Self, BodyRejection>;
+}
+
+/// Ignore the body.
+impl<'r> ExtractBody<'r> for () {
+    fn extract(_body: &'r RequestBody) -> Result<Self, BodyRejection> {
+        Ok(())
+    }
+}
+
+/// Raw bytes of the body.
+impl<'r> ExtractBody<'r> for &'r [u8] {
+    fn extract(body: &'r RequestBody) -> Result<Self, BodyRejection> {
+        Ok(body.bytes())
+    }
+}
+
+impl<'r> ExtractBody<'r> for Vec<u8> {
+    fn extract(body: &'r RequestBody) -> Result<Self, BodyRejection> {
+        Ok(body.bytes().to_vec())
+    }
+}
+
+/// Body as text, rejected if not UTF-8.
+impl<'r> ExtractBody<'r> for &'r str {
+    fn extract(body: &'r RequestBody) -> Result<Self, BodyRejection> {
+        body.text()
+    }
+}
+
+impl<'r> ExtractBody<'r> for String {
+    fn extract(body: &'r RequestBody) -> Result<Self, BodyRejection> {
+        body.text().map(str::to_owned)
+    }
+}
+
+/// Empty body as `None`.
+impl<'r, T: ExtractBody<'r>> ExtractBody<'r> for Option<T> {
+    fn extract(body: &'r RequestBody) -> Result<Self, BodyRejection> {
+        if body.is_empty() {
+            Ok(None)
+        } else {
+            T::extract(body).map(Some)
+        }
+    }
+}
+
+/// Body deserialized from JSON, the content type must be `application/json`.
+impl<'r, T: DeserializeOwned + Send> ExtractBody<'r> for Json<T> {
+    fn extract(body: &'r RequestBody) -> Result<Self, BodyRejection> {
+        body.expect_media_type("application/json")?;
+        serde_json::from_slice(body.bytes())
+            .map(Json)
+            .map_err(|err| BodyRejection::Invalid(format!("Invalid JSON body: {}", err)))
+    }
+}
+
+/// Body deserialized from an url encoded form,
+/// the content type must be `application/x-www-form-urlencoded`.
+pub struct Form<T>(pub T);
+
+impl<'r, T: DeserializeOwned + Send> ExtractBody<'r> for Form<T> {
+    fn extract(body: &'r RequestBody) -> Result<Self, BodyRejection> {
+        body.expect_media_type("application/x-www-form-urlencoded")?;
+        serde_urlencoded::from_bytes(body.bytes())
+            .map(Form)
+            .map_err(|err| BodyRejection::Invalid(format!("Invalid form body: {}", err)))
+    }
+}
+
+/// Headers of a request, names are case insensitive.
+#[derive(Debug, Default, Clone)]
+pub struct Headers(Vec<(String, String)>);
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a header, keeping the previous values with the same name.
+    pub fn append<N: Into<String>, V: Into<String>>(&mut self, name: N, value: V) {
+        self.0.push((name.into(), value.into()));
+    }
+
+    /// First value of the header.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.0
+            .iter()
+            .filter(move |(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// First value of the header, rejecting the request if missing.
+    pub fn require(&self, name: &str) -> Result<&str, RequestRejection> {
+        self.get(name)
+            .ok_or_else(|| RequestRejection::MissingHeader(name.to_owned()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+impl<N: Into<String>, V: Into<String>> FromIterator<(N, V)> for Headers {
+    fn from_iter<T: IntoIterator<Item = (N, V)>>(iter: T) -> Self {
+        let headers = iter
+            .into_iter()
+            .map(|(name, value)| (name.into(), value.into()))
+            .collect();
+        Headers(headers)
+    }
+}
+
+/// Cookies of a request, parsed from the `Cookie` headers.
+#[derive(Debug, Default, Clone)]
+pub struct Cookies(HashMap<String, String>);
+
+impl Cookies {
+    pub fn parse(headers: &Headers) -> Self {
+        let cookies = headers
+            .get_all("cookie")
+            .flat_map(|header| header.split(';'))
+            // silently ignore malformed cookies, like the url params.
+            .filter_map(|cookie| cookie.split_once('='))
+            .map(|(name, value)| {
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+                (name.trim().to_owned(), value.to_owned())
+            })
+            .collect();
+        Cookies(cookies)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// Value of the cookie, rejecting the request if missing.
+    pub fn require(&self, name: &str) -> Result<&str, RequestRejection> {
+        self.get(name)
+            .ok_or_else(|| RequestRejection::MissingCookie(name.to_owned()))
+    }
+}
+
+/// Query string deserialized from an url encoded form.
+/// A missing query string is deserialized as an empty one.
+pub struct Query<T>(pub T);
+
+/// Reason the data of a request was rejected, turned into a 400 response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestRejection {
+    MissingHeader(String),
+    InvalidHeader { name: String, details: String },
+    MissingCookie(String),
+    InvalidQuery(String),
+}
+
+impl UserError for RequestRejection {
+    fn status(&self) -> u16 {
+        400
+    }
+
+    fn public_message(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl Display for RequestRejection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RequestRejection::MissingHeader(name) => write!(f, "Missing header {}.", name),
+            RequestRejection::InvalidHeader { name, details } => {
+                write!(f, "Invalid header {}: {}", name, details)
+            }
+            RequestRejection::MissingCookie(name) => write!(f, "Missing cookie {}.", name),
+            RequestRejection::InvalidQuery(details) => {
+                write!(f, "Invalid query string: {}", details)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RequestRejection {}
+
+impl From<RequestRejection> for StonkksError {
+    fn from(rejection: RequestRejection) -> Self {
+        StonkksError::User(ErrorReport::from_user_error(&rejection))
+    }
+}
+
+/// Data of a request available to the extractors, everything but the body.
+#[derive(Clone, Copy)]
+pub struct RequestParts<'r> {
+    pub states: &'r StatesMap,
+    pub headers: &'r Headers,
+    pub query: Option<&'r str>,
+}
+
+/// Extractor used to access the states of the server and the data of the request
+/// in `Api::respond`, implemented for tuples of extractors.
+/// Missing states are reported as internal errors, invalid request data with a 400.
+pub trait ExtractRequest<'r>: Sized + Send {
+    fn extract(parts: RequestParts<'r>) -> Result<Self, StonkksError>;
+}
+
+impl<'r> ExtractRequest<'r> for () {
+    fn extract(_parts: RequestParts<'r>) -> Result<Self, StonkksError> {
+        Ok(())
+    }
+}
+
+impl<'r, T: ExtractState<'r>> ExtractRequest<'r> for State<T> {
+    fn extract(parts: RequestParts<'r>) -> Result<Self, StonkksError> {
+        parts
+            .states
+            .extract::<T>()
+            .map(State)
+            .map_err(StonkksError::MissingState)
+    }
+}
+
+/// A reference is extracted as a state, like in `ExtractState`,
+/// so `&'r Headers` is a state, the headers of the request are extracted with `Headers`.
+impl<'r, T: AnyState> ExtractRequest<'r> for &'r T {
+    fn extract(parts: RequestParts<'r>) -> Result<Self, StonkksError> {
+        <&'r T as ExtractState<'r>>::extract(parts.states).map_err(StonkksError::MissingState)
+    }
+}
+
+impl<'r> ExtractRequest<'r> for Headers {
+    fn extract(parts: RequestParts<'r>) -> Result<Self, StonkksError> {
+        Ok(parts.headers.clone())
+    }
+}
+
+impl<'r> ExtractRequest<'r> for Cookies {
+    fn extract(parts: RequestParts<'r>) -> Result<Self, StonkksError> {
+        Ok(Cookies::parse(parts.headers))
+    }
+}
+
+impl<'r, T: DeserializeOwned + Send> ExtractRequest<'r> for Query<T> {
+    fn extract(parts: RequestParts<'r>) -> Result<Self, StonkksError> {
+        let query = parts.query.unwrap_or_default();
+        serde_urlencoded::from_str(query)
+            .map(Query)
+            .map_err(|err| RequestRejection::InvalidQuery(err.to_string()).into())
+    }
+}
+
+mod impl_macro {
+    use super::*;
+
+    // same as the one for `ExtractState`.
+    macro_rules! tuple_impls {
+        // Stopping criteria (1-ary tuple)
+        ($T:ident) => {
+            tuple_impls!(@impl $T);
+        };
+        // Running criteria (n-ary tuple, with n >= 2)
+        ($T:ident $( $U:ident )+) => {
+            tuple_impls!($( $U )+);
+            tuple_impls!(@impl $T $( $U )+);
+        };
+        // "Private" internal implementation
+        (@impl $( $T:ident )+) => {
+            impl<'r, $($T: ExtractRequest<'r>),+> ExtractRequest<'r> for ($($T,)+) {
+                fn extract(parts: RequestParts<'r>) -> Result<Self, StonkksError> {
+                    Ok(($($T::extract(parts)?,)+))
+                }
+            }
+        }
+    }
+
+    tuple_impls!(A B C D E F G H I J K L M N O P); // 16 Max
+}
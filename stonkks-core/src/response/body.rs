@@ -0,0 +1,82 @@
+use std::convert::Infallible;
+use std::io;
+
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+
+use super::{ContentType, IntoResponse, Response};
+
+/// Stream of byte chunks of a streamed response, an error ends the response.
+pub type BodyStream = BoxStream<'static, io::Result<Vec<u8>>>;
+
+/// Body of a response, either fully buffered or streamed.
+/// Adapters must forward the chunks of a stream as they come.
+pub enum ResponseBody {
+    Full(Vec<u8>),
+    Stream(BodyStream),
+}
+
+impl ResponseBody {
+    /// Content of a buffered body, `None` for a stream.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            ResponseBody::Full(bytes) => Some(bytes),
+            ResponseBody::Stream(_) => None,
+        }
+    }
+
+    pub fn is_stream(&self) -> bool {
+        matches!(self, ResponseBody::Stream(_))
+    }
+
+    /// The body as a stream, a buffered body is sent as a single chunk.
+    pub fn into_stream(self) -> BodyStream {
+        match self {
+            ResponseBody::Full(bytes) => stream::once(async move { Ok(bytes) }).boxed(),
+            ResponseBody::Stream(stream) => stream,
+        }
+    }
+}
+
+impl From<Vec<u8>> for ResponseBody {
+    fn from(bytes: Vec<u8>) -> Self {
+        ResponseBody::Full(bytes)
+    }
+}
+
+/// Response streamed chunk by chunk, `application/octet-stream` by default.
+///
+/// ```ignore
+/// let rows = stream::iter(rows).map(|row| Ok::<_, io::Error>(format!("{}\n", row)));
+/// Streaming::new(rows).with_content_type(ContentType::CSV)
+/// ```
+pub struct Streaming {
+    stream: BodyStream,
+    content_type: ContentType,
+}
+
+impl Streaming {
+    pub fn new<S, B, E>(stream: S) -> Self
+    where
+        S: Stream<Item = Result<B, E>> + Send + 'static,
+        B: Into<Vec<u8>>,
+        E: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let stream = stream.map(|chunk| chunk.map(Into::into).map_err(io::Error::other));
+        Streaming {
+            stream: stream.boxed(),
+            content_type: ContentType::OCTET_STREAM,
+        }
+    }
+
+    pub fn with_content_type(mut self, content_type: ContentType) -> Self {
+        self.content_type = content_type;
+        self
+    }
+}
+
+impl IntoResponse for Streaming {
+    type Err = Infallible;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        Ok(Response::stream(self.content_type, self.stream))
+    }
+}
@@ -0,0 +1,82 @@
+use std::borrow::Cow;
+use std::fmt::Display;
+
+/// MIME type of a response, with an optional charset.
+///
+/// Common types are available as constants, the textual ones use the UTF-8 charset.
+/// Any other type can be built with `ContentType::new`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ContentType {
+    mime: Cow<'static, str>,
+    charset: Option<Cow<'static, str>>,
+}
+
+const UTF_8: Option<Cow<'static, str>> = Some(Cow::Borrowed("utf-8"));
+
+impl ContentType {
+    pub const TEXT: ContentType = ContentType::utf8("text/plain");
+    pub const HTML: ContentType = ContentType::utf8("text/html");
+    pub const CSS: ContentType = ContentType::utf8("text/css");
+    pub const CSV: ContentType = ContentType::utf8("text/csv");
+    pub const XML: ContentType = ContentType::utf8("application/xml");
+    pub const JAVASCRIPT: ContentType = ContentType::utf8("text/javascript");
+    pub const EVENT_STREAM: ContentType = ContentType::utf8("text/event-stream");
+    pub const JSON: ContentType = ContentType::binary("application/json");
+    pub const OCTET_STREAM: ContentType = ContentType::binary("application/octet-stream");
+    pub const PDF: ContentType = ContentType::binary("application/pdf");
+    pub const PNG: ContentType = ContentType::binary("image/png");
+    pub const JPEG: ContentType = ContentType::binary("image/jpeg");
+    pub const GIF: ContentType = ContentType::binary("image/gif");
+    pub const SVG: ContentType = ContentType::binary("image/svg+xml");
+    pub const WEBP: ContentType = ContentType::binary("image/webp");
+
+    const fn utf8(mime: &'static str) -> Self {
+        ContentType {
+            mime: Cow::Borrowed(mime),
+            charset: UTF_8,
+        }
+    }
+
+    const fn binary(mime: &'static str) -> Self {
+        ContentType {
+            mime: Cow::Borrowed(mime),
+            charset: None,
+        }
+    }
+
+    /// Content type from a MIME type, like `application/vnd.ms-excel`, without charset.
+    pub fn new<T: Into<Cow<'static, str>>>(mime: T) -> Self {
+        ContentType {
+            mime: mime.into(),
+            charset: None,
+        }
+    }
+
+    pub fn with_charset<T: Into<Cow<'static, str>>>(mut self, charset: T) -> Self {
+        self.charset = Some(charset.into());
+        self
+    }
+
+    pub fn without_charset(mut self) -> Self {
+        self.charset = None;
+        self
+    }
+
+    pub fn mime(&self) -> &str {
+        &self.mime
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.charset.as_deref()
+    }
+}
+
+/// Value of the `Content-Type` header (`text/html; charset=utf-8`).
+impl Display for ContentType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.charset {
+            Some(charset) => write!(f, "{}; charset={}", self.mime, charset),
+            None => f.write_str(&self.mime),
+        }
+    }
+}
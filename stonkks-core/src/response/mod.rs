@@ -1,24 +1,129 @@
 use serde::Serialize;
-use std::fmt::Debug;
+use std::convert::Infallible;
+use std::fmt::{Debug, Write};
 
-pub enum ContentType {
-    Text,
-    Json,
+use crate::request::Headers;
+
+mod body;
+mod content_type;
+mod sse;
+
+pub use body::{BodyStream, ResponseBody, Streaming};
+pub use content_type::ContentType;
+pub use sse::{KeepAlive, Sse, SseEvent};
+
+/// HTTP status code of a response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Status(pub u16);
+
+impl Status {
+    pub const SWITCHING_PROTOCOLS: Status = Status(101);
+    pub const OK: Status = Status(200);
+    pub const CREATED: Status = Status(201);
+    pub const ACCEPTED: Status = Status(202);
+    pub const NO_CONTENT: Status = Status(204);
+    pub const BAD_REQUEST: Status = Status(400);
+    pub const UNAUTHORIZED: Status = Status(401);
+    pub const FORBIDDEN: Status = Status(403);
+    pub const NOT_FOUND: Status = Status(404);
+    pub const CONFLICT: Status = Status(409);
+    pub const INTERNAL_SERVER_ERROR: Status = Status(500);
+
+    pub fn code(self) -> u16 {
+        self.0
+    }
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Status::OK
+    }
 }
 
 pub struct Response {
-    pub content_type: ContentType,
-    pub content: Vec<u8>,
+    pub status: Status,
+    pub headers: Headers,
+    /// `None` for an empty response.
+    pub content_type: Option<ContentType>,
+    pub content: ResponseBody,
 }
 
 impl Response {
-    fn new<C: Into<Vec<u8>>>(content_type: ContentType, content: C) -> Self {
-        let content = content.into();
+    pub fn new<C: Into<Vec<u8>>>(content_type: ContentType, content: C) -> Self {
         Response {
-            content_type,
-            content,
+            status: Status::OK,
+            headers: Headers::new(),
+            content_type: Some(content_type),
+            content: ResponseBody::Full(content.into()),
+        }
+    }
+
+    /// Streamed response, see `Streaming` and `Sse`.
+    pub fn stream(content_type: ContentType, stream: BodyStream) -> Self {
+        Response {
+            status: Status::OK,
+            headers: Headers::new(),
+            content_type: Some(content_type),
+            content: ResponseBody::Stream(stream),
+        }
+    }
+
+    /// Response without a body.
+    pub fn empty(status: Status) -> Self {
+        Response {
+            status,
+            headers: Headers::new(),
+            content_type: None,
+            content: ResponseBody::Full(Vec::new()),
         }
     }
+
+    pub fn with_status(mut self, status: Status) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub fn with_header<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.headers.append(name, value);
+        self
+    }
+
+    /// Ask the browser to download the response as a file with the given name,
+    /// with a `Content-Disposition: attachment` header.
+    pub fn attachment(self, filename: &str) -> Self {
+        self.with_header("Content-Disposition", content_disposition(filename))
+    }
+}
+
+/// Value of the `Content-Disposition` header of an attachment.
+/// Non ASCII names are also sent percent encoded (RFC 6266), for the browsers supporting it.
+fn content_disposition(filename: &str) -> String {
+    let ascii_name: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            c if c.is_ascii() && !c.is_ascii_control() => c,
+            _ => '_',
+        })
+        .collect();
+    if ascii_name == filename {
+        return format!("attachment; filename=\"{}\"", ascii_name);
+    }
+    let mut encoded = String::new();
+    for byte in filename.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'.' | b'-' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => {
+                let _ = write!(encoded, "%{:02X}", byte);
+            }
+        }
+    }
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        ascii_name, encoded
+    )
 }
 
 pub trait IntoResponse {
@@ -26,12 +131,165 @@ pub trait IntoResponse {
     fn into_response(self) -> Result<Response, Self::Err>;
 }
 
-pub struct Json<T: Serialize>(pub T);
+impl IntoResponse for Response {
+    type Err = Infallible;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        Ok(self)
+    }
+}
+
+/// JSON response, or JSON request body when used as an `ExtractBody`.
+pub struct Json<T>(pub T);
 
 impl<T: Serialize> IntoResponse for Json<T> {
     type Err = serde_json::Error;
     fn into_response(self) -> Result<Response, Self::Err> {
         let serialized_value = serde_json::to_string(&self.0)?;
-        Ok(Response::new(ContentType::Json, serialized_value))
+        Ok(Response::new(ContentType::JSON, serialized_value))
+    }
+}
+
+/// HTML response, like a fragment rendered for an htmx style api.
+/// Not part of the prelude, the name clashes with the `Html` trait of sycamore.
+pub struct Html<T>(pub T);
+
+impl<T: Into<String>> IntoResponse for Html<T> {
+    type Err = Infallible;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        Ok(Response::new(ContentType::HTML, self.0.into()))
+    }
+}
+
+/// Binary response of any content type, `application/octet-stream` by default.
+pub struct Bytes {
+    pub content: Vec<u8>,
+    pub content_type: ContentType,
+}
+
+impl Bytes {
+    pub fn new<C: Into<Vec<u8>>>(content: C) -> Self {
+        Bytes {
+            content: content.into(),
+            content_type: ContentType::OCTET_STREAM,
+        }
+    }
+
+    pub fn with_content_type(mut self, content_type: ContentType) -> Self {
+        self.content_type = content_type;
+        self
+    }
+}
+
+impl IntoResponse for Bytes {
+    type Err = Infallible;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        Ok(Response::new(self.content_type, self.content))
+    }
+}
+
+/// Response downloaded by the browser as a file, see `Response::attachment`.
+///
+/// ```ignore
+/// let csv = Bytes::new(export).with_content_type(ContentType::CSV);
+/// Attachment::new("export.csv", csv)
+/// ```
+pub struct Attachment<T> {
+    pub filename: String,
+    pub content: T,
+}
+
+impl<T> Attachment<T> {
+    pub fn new<N: Into<String>>(filename: N, content: T) -> Self {
+        Attachment {
+            filename: filename.into(),
+            content,
+        }
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for Attachment<T> {
+    type Err = T::Err;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        Ok(self.content.into_response()?.attachment(&self.filename))
+    }
+}
+
+impl IntoResponse for String {
+    type Err = Infallible;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        Ok(Response::new(ContentType::TEXT, self))
+    }
+}
+
+impl IntoResponse for &'static str {
+    type Err = Infallible;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        Ok(Response::new(ContentType::TEXT, self))
+    }
+}
+
+impl IntoResponse for Vec<u8> {
+    type Err = Infallible;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        Ok(Response::new(ContentType::OCTET_STREAM, self))
+    }
+}
+
+/// Empty 204 response.
+impl IntoResponse for () {
+    type Err = Infallible;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        Ok(Response::empty(Status::NO_CONTENT))
+    }
+}
+
+/// Empty 404 response for `None`.
+impl<T: IntoResponse> IntoResponse for Option<T> {
+    type Err = T::Err;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        match self {
+            Some(value) => value.into_response(),
+            None => Ok(Response::empty(Status::NOT_FOUND)),
+        }
+    }
+}
+
+/// Error of the response of a `Result`, from the variant it was built from.
+#[derive(Debug)]
+pub enum ResultResponseError<T, E> {
+    Ok(T),
+    Err(E),
+}
+
+/// Both variants are responses, the error one usually sets an error status
+/// with `(Status, T)`.
+impl<T: IntoResponse, E: IntoResponse> IntoResponse for Result<T, E> {
+    type Err = ResultResponseError<T::Err, E::Err>;
+    fn into_response(self) -> Result<Response, <Self as IntoResponse>::Err> {
+        match self {
+            Ok(value) => value.into_response().map_err(ResultResponseError::Ok),
+            Err(err) => err.into_response().map_err(ResultResponseError::Err),
+        }
+    }
+}
+
+impl<T: IntoResponse> IntoResponse for (Status, T) {
+    type Err = T::Err;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        let (status, value) = self;
+        Ok(value.into_response()?.with_status(status))
+    }
+}
+
+/// The headers are added to the ones of the response.
+impl<T: IntoResponse> IntoResponse for (Status, Headers, T) {
+    type Err = T::Err;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        let (status, headers, value) = self;
+        let mut response = value.into_response()?.with_status(status);
+        for (name, value) in headers.iter() {
+            response.headers.append(name, value);
+        }
+        Ok(response)
     }
 }
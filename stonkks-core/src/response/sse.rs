@@ -0,0 +1,217 @@
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::stream::{BoxStream, Stream, StreamExt};
+use futures::FutureExt;
+use futures_timer::Delay;
+use serde::Serialize;
+
+use super::{BodyStream, ContentType, IntoResponse, Response};
+
+/// Event of a Server-Sent Events response, see `Sse`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    event: Option<String>,
+    id: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+    comment: Option<String>,
+}
+
+impl SseEvent {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Data of the event, multiline data is sent as multiple `data` fields.
+    pub fn data<T: Into<String>>(mut self, data: T) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Data of the event serialized as JSON.
+    pub fn json<T: Serialize>(self, value: &T) -> Result<Self, serde_json::Error> {
+        let data = serde_json::to_string(value)?;
+        Ok(self.data(data))
+    }
+
+    /// Type of the event, dispatched to the matching `addEventListener` on the client.
+    pub fn event<T: Into<String>>(mut self, event: T) -> Self {
+        self.event = Some(single_line(event.into()));
+        self
+    }
+
+    /// Id of the event, sent back by the browser in the `Last-Event-ID` header on reconnection.
+    pub fn id<T: Into<String>>(mut self, id: T) -> Self {
+        self.id = Some(single_line(event_id(id.into())));
+        self
+    }
+
+    /// Delay the browser waits before reconnecting.
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    /// Comment, ignored by the browser.
+    pub fn comment<T: Into<String>>(mut self, comment: T) -> Self {
+        self.comment = Some(single_line(comment.into()));
+        self
+    }
+
+    /// Event in the `text/event-stream` format, ending with a blank line.
+    pub fn encode(&self) -> String {
+        let mut encoded = String::new();
+        if let Some(comment) = &self.comment {
+            let _ = writeln!(encoded, ":{}", comment);
+        }
+        if let Some(event) = &self.event {
+            let _ = writeln!(encoded, "event:{}", event);
+        }
+        if let Some(id) = &self.id {
+            let _ = writeln!(encoded, "id:{}", id);
+        }
+        if let Some(retry) = self.retry {
+            let _ = writeln!(encoded, "retry:{}", retry.as_millis());
+        }
+        if let Some(data) = &self.data {
+            for line in data.split('\n') {
+                let line = line.strip_suffix('\r').unwrap_or(line);
+                let _ = writeln!(encoded, "data:{}", line);
+            }
+        }
+        encoded.push('\n');
+        encoded
+    }
+}
+
+/// Line breaks would start a new field.
+fn single_line(value: String) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+/// Browsers ignore ids containing a null character.
+fn event_id(id: String) -> String {
+    id.replace('\0', "")
+}
+
+/// Comment sent when no event was sent for a while, so proxies don't close
+/// the connection. See `Sse::keep_alive`.
+#[derive(Debug, Clone)]
+pub struct KeepAlive {
+    interval: Duration,
+    text: String,
+}
+
+impl KeepAlive {
+    /// Every 15 seconds by default.
+    pub fn new() -> Self {
+        KeepAlive {
+            interval: Duration::from_secs(15),
+            text: String::new(),
+        }
+    }
+
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn text<T: Into<String>>(mut self, text: T) -> Self {
+        self.text = text.into();
+        self
+    }
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Server-Sent Events response, the stream ends when the response ends.
+///
+/// ```ignore
+/// let events = ticks.map(|count| SseEvent::new().event("tick").id(count.to_string()).data("..."));
+/// Sse::new(events).keep_alive(KeepAlive::new())
+/// ```
+pub struct Sse {
+    events: BoxStream<'static, SseEvent>,
+    keep_alive: Option<KeepAlive>,
+}
+
+impl Sse {
+    pub fn new<S: Stream<Item = SseEvent> + Send + 'static>(events: S) -> Self {
+        Sse {
+            events: events.boxed(),
+            keep_alive: None,
+        }
+    }
+
+    pub fn keep_alive(mut self, keep_alive: KeepAlive) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Stream of the events, with the keep alive comments.
+    pub fn into_events(self) -> BoxStream<'static, SseEvent> {
+        match self.keep_alive {
+            Some(keep_alive) => KeepAliveStream::new(self.events, keep_alive).boxed(),
+            None => self.events,
+        }
+    }
+}
+
+impl IntoResponse for Sse {
+    type Err = Infallible;
+    fn into_response(self) -> Result<Response, Self::Err> {
+        let stream: BodyStream = self
+            .into_events()
+            .map(|event| Ok(event.encode().into_bytes()))
+            .boxed();
+        let response = Response::stream(ContentType::EVENT_STREAM, stream)
+            .with_header("Cache-Control", "no-cache");
+        Ok(response)
+    }
+}
+
+/// Emit the keep alive comment when no event was emitted during the interval.
+struct KeepAliveStream {
+    events: BoxStream<'static, SseEvent>,
+    keep_alive: KeepAlive,
+    delay: Delay,
+}
+
+impl KeepAliveStream {
+    fn new(events: BoxStream<'static, SseEvent>, keep_alive: KeepAlive) -> Self {
+        let delay = Delay::new(keep_alive.interval);
+        KeepAliveStream {
+            events,
+            keep_alive,
+            delay,
+        }
+    }
+}
+
+impl Stream for KeepAliveStream {
+    type Item = SseEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let interval = self.keep_alive.interval;
+        if let Poll::Ready(event) = self.events.poll_next_unpin(cx) {
+            self.delay.reset(interval);
+            return Poll::Ready(event);
+        }
+        match self.delay.poll_unpin(cx) {
+            Poll::Ready(()) => {
+                self.delay.reset(interval);
+                let comment = SseEvent::new().comment(self.keep_alive.text.clone());
+                Poll::Ready(Some(comment))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
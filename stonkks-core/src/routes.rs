@@ -27,7 +27,7 @@ impl<'a, 'url> Deref for Params<'a, 'url> {
     type Target = HashMap<&'url str, &'url str>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        self.0
     }
 }
 
@@ -37,7 +37,7 @@ impl<'a> Deref for OwnedSegments<'a> {
     type Target = [&'a str];
 
     fn deref(&self) -> &Self::Target {
-        &*self.0
+        &self.0
     }
 }
 
@@ -52,12 +52,10 @@ impl<'a> OwnedParams<'a> {
     pub fn parse(params: &'a str) -> Self {
         let mut params_map = HashMap::new();
         let iter = params.split('&').map(|segment| segment.split_once('='));
-        for params in iter {
-            // silently ignore malformed params
-            // TODO: figure out what to do in this case.
-            if let Some((name, value)) = params {
-                params_map.insert(name, value);
-            }
+        // silently ignore malformed params
+        // TODO: figure out what to do in this case.
+        for (name, value) in iter.flatten() {
+            params_map.insert(name, value);
         }
         OwnedParams(params_map)
     }
@@ -74,13 +72,13 @@ impl<'a, 'url> Deref for Segments<'a, 'url> {
     type Target = [&'url str];
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        self.0
     }
 }
 
 impl<'a> OwnedSegments<'a> {
     pub fn to_shared(&self) -> Segments<'_, 'a> {
-        Segments(&*self)
+        Segments(self)
     }
 
     pub fn to_shared_shifted(&self) -> Option<(&'a str, Segments<'_, 'a>)> {
@@ -91,7 +89,7 @@ impl<'a> OwnedSegments<'a> {
     }
 }
 
-fn parse_segments<'a>(path: &'a str) -> OwnedSegments {
+fn parse_segments(path: &str) -> OwnedSegments<'_> {
     path.split('/')
         .filter(|s| !s.is_empty())
         .map(|s| {
@@ -188,6 +186,11 @@ impl<'a, 'url> UrlInfos<'a, 'url> {
     pub fn url(&self) -> &'url str {
         self.url
     }
+
+    /// Raw query string of the url, without the `?`.
+    pub fn query(&self) -> Option<&'url str> {
+        self.url.split_once('?').map(|(_, query)| query)
+    }
 }
 
 pub trait Routable: Send + Sync + 'static {
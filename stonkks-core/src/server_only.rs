@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sycamore::prelude::*;
+
+/// Attribute holding the key of a server only part on it's root element.
+pub const SERVER_ONLY_ATTRIBUTE: &str = "data-stonkks-server-only";
+
+/// Props field only available on the server, e.g. a large markdown source rendered to html.
+///
+/// The value is never serialized, so it is not part of the props sent to the client.
+/// It can only be used through `ServerOnly::render`, which render it to static html on the server,
+/// the client reuse the html present in the document instead of rendering it.
+///
+/// Each server only part of a page is identified by the key given to `ServerOnly::render`,
+/// usually the name of the field, it must be unique in the page. When hydrating, a key
+/// without html in the document, or html left without a matching key, fails the hydration.
+/// Props deserialized from the props route, and the props of pages rendered with
+/// `RenderMode::ClientOnly`, never have the value, so they render empty parts.
+pub struct ServerOnly<T>(Option<T>);
+
+impl<T> ServerOnly<T> {
+    pub fn new(value: T) -> Self {
+        ServerOnly(Some(value))
+    }
+
+    /// Whether the value is present, always false on the client.
+    pub fn is_available(&self) -> bool {
+        self.0.is_some()
+    }
+
+    /// Render the value on the server, in an element identified by `key`.
+    /// On the client the html rendered by the server for the same key is kept as is.
+    ///
+    /// Takes a reference, so it can also be called on a field wrapped in a signal by
+    /// `derive(Props)`, through `props.field.get()`.
+    pub fn render<G, F>(&self, cx: Scope, key: &'static str, render: F) -> View<G>
+    where
+        G: Html,
+        F: for<'b> FnOnce(Scope<'b>, &T) -> View<SsrNode>,
+    {
+        let context = try_use_context::<ServerOnlyContext>(cx);
+        if let Some(context) = context {
+            context.register(key);
+        }
+        let html = match (G::IS_BROWSER, &self.0) {
+            (false, Some(value)) => sycamore::render_to_string(|cx| render(cx, value)),
+            (true, _) => context
+                .and_then(|context| context.take_content(key))
+                .unwrap_or_default(),
+            (false, None) => String::new(),
+        };
+        view! { cx,
+            div(data-stonkks-server-only=key, dangerously_set_inner_html=&html)
+        }
+    }
+}
+
+impl<T> Default for ServerOnly<T> {
+    fn default() -> Self {
+        ServerOnly(None)
+    }
+}
+
+impl<T> From<T> for ServerOnly<T> {
+    fn from(value: T) -> Self {
+        ServerOnly::new(value)
+    }
+}
+
+/// Serialized as a unit, the value never leaves the server.
+impl<T> Serialize for ServerOnly<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_unit()
+    }
+}
+
+impl<'de, T> Deserialize<'de> for ServerOnly<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <()>::deserialize(deserializer)?;
+        Ok(ServerOnly(None))
+    }
+}
+
+/// Context provided at the root of a page render, tracking the keys of the server only parts.
+/// On the client it holds the html of each server only part present in the document,
+/// and the parts that could not be matched with it.
+#[derive(Clone, Default)]
+pub struct ServerOnlyContext(Rc<RefCell<ServerOnlyParts>>);
+
+#[derive(Default)]
+struct ServerOnlyParts {
+    contents: HashMap<String, String>,
+    keys: HashSet<&'static str>,
+    errors: Vec<String>,
+}
+
+impl ServerOnlyContext {
+    pub fn new(contents: HashMap<String, String>) -> Self {
+        let parts = ServerOnlyParts {
+            contents,
+            ..Default::default()
+        };
+        ServerOnlyContext(Rc::new(RefCell::new(parts)))
+    }
+
+    fn register(&self, key: &'static str) {
+        let mut parts = self.0.borrow_mut();
+        if !parts.keys.insert(key) {
+            parts
+                .errors
+                .push(format!("server only key \"{}\" is used twice", key));
+        }
+    }
+
+    fn take_content(&self, key: &'static str) -> Option<String> {
+        let mut parts = self.0.borrow_mut();
+        let content = parts.contents.remove(key);
+        if content.is_none() {
+            parts.errors.push(format!(
+                "no server rendered html for the server only key \"{}\"",
+                key
+            ));
+        }
+        content
+    }
+
+    /// Check that every server only part of the document was matched by a single
+    /// `ServerOnly::render`, called once the page is hydrated.
+    pub fn check_hydrated(&self) -> Result<(), String> {
+        let parts = self.0.borrow();
+        let extra = parts.contents.keys().map(|key| {
+            format!(
+                "no server only part for the server rendered key \"{}\"",
+                key
+            )
+        });
+        let errors: Vec<_> = parts.errors.iter().cloned().chain(extra).collect();
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join(", "))
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::Display;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::codec::{CodecError, PropsCodec};
+use crate::errors::StonkksError;
+use crate::pointers::*;
+use crate::predule::*;
+use crate::request::{ExtractRequest, RequestParts};
+use crate::routes::DynRoutable;
+
+/// Message of a WebSocket connection.
+/// Ping and pong messages are handled by the adapters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WsMessage {
+    Text(String),
+    Binary(Vec<u8>),
+    Close,
+}
+
+#[derive(Debug, Clone)]
+pub enum WsError {
+    /// The connection is closed.
+    Closed,
+    /// Error of the underlying connection, reported by the adapter.
+    Transport(String),
+    /// A message could not be encoded or decoded with the codec of the route.
+    Codec(Arc<CodecError>),
+    /// The message is text for a binary codec, or binary for a textual codec.
+    UnexpectedMessage,
+}
+
+impl Display for WsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WsError::Closed => f.write_str("The connection is closed."),
+            WsError::Transport(details) => write!(f, "WebSocket error: {}", details),
+            WsError::Codec(err) => write!(f, "Invalid message: {}", err),
+            WsError::UnexpectedMessage => {
+                f.write_str("Unexpected message type for the codec of the route.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WsError {}
+
+/// Untyped WebSocket connection, built by the adapters from the upgraded connection.
+pub struct WebSocket {
+    incoming: BoxStream<'static, Result<WsMessage, WsError>>,
+    outgoing: Pin<Box<dyn Sink<WsMessage, Error = WsError> + Send>>,
+}
+
+impl WebSocket {
+    pub fn new<I, O>(incoming: I, outgoing: O) -> Self
+    where
+        I: Stream<Item = Result<WsMessage, WsError>> + Send + 'static,
+        O: Sink<WsMessage, Error = WsError> + Send + 'static,
+    {
+        WebSocket {
+            incoming: incoming.boxed(),
+            outgoing: Box::pin(outgoing),
+        }
+    }
+
+    /// Next message, `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Option<Result<WsMessage, WsError>> {
+        match self.incoming.next().await? {
+            Ok(WsMessage::Close) => None,
+            message => Some(message),
+        }
+    }
+
+    pub async fn send(&mut self, message: WsMessage) -> Result<(), WsError> {
+        self.outgoing.send(message).await
+    }
+
+    pub async fn close(mut self) -> Result<(), WsError> {
+        self.outgoing.send(WsMessage::Close).await?;
+        self.outgoing.close().await
+    }
+}
+
+/// Typed messages over a WebSocket, encoded with the codec of the route.
+/// Textual codecs use text messages, binary ones binary messages.
+pub struct WsChannel<In, Out> {
+    socket: WebSocket,
+    codec: PropsCodec,
+    _marker: PhantomData<fn(Out) -> In>,
+}
+
+impl<In: DeserializeOwned, Out: Serialize> WsChannel<In, Out> {
+    pub fn new(socket: WebSocket, codec: PropsCodec) -> Self {
+        WsChannel {
+            socket,
+            codec,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Next message, `None` once the connection is closed.
+    pub async fn recv(&mut self) -> Option<Result<In, WsError>> {
+        let message = match self.socket.recv().await? {
+            Ok(message) => message,
+            Err(err) => return Some(Err(err)),
+        };
+        let decoded = match message {
+            WsMessage::Text(text) if !self.codec.is_binary() => {
+                self.codec.decode_bytes(text.as_bytes())
+            }
+            WsMessage::Binary(bytes) if self.codec.is_binary() => self.codec.decode_bytes(&bytes),
+            _ => return Some(Err(WsError::UnexpectedMessage)),
+        };
+        Some(decoded.map_err(|err| WsError::Codec(Arc::new(err))))
+    }
+
+    pub async fn send(&mut self, message: &Out) -> Result<(), WsError> {
+        let message = encode_message(self.codec, message)?;
+        self.socket.send(message).await
+    }
+
+    pub async fn close(self) -> Result<(), WsError> {
+        self.socket.close().await
+    }
+
+    /// The untyped connection.
+    pub fn into_socket(self) -> WebSocket {
+        self.socket
+    }
+}
+
+/// Encode a message with the codec, used by both ends of the connection.
+pub fn encode_message<T: Serialize>(codec: PropsCodec, message: &T) -> Result<WsMessage, WsError> {
+    let bytes = codec
+        .encode_bytes(message)
+        .map_err(|err| WsError::Codec(Arc::new(err)))?;
+    if codec.is_binary() {
+        Ok(WsMessage::Binary(bytes))
+    } else {
+        // textual codecs produce UTF-8.
+        let text = String::from_utf8(bytes).map_err(|_| WsError::UnexpectedMessage)?;
+        Ok(WsMessage::Text(text))
+    }
+}
+
+/// Trait used to create a WebSocket route, served under `/ws/`.
+///
+/// The messages types are shared by the server and the client,
+/// see `Client::connect_websocket`.
+#[async_trait::async_trait]
+pub trait WebSocketRoute: Routable {
+    /// Extractor used to access states of the server and the data of the upgrade request,
+    /// see `ExtractRequest`.
+    type State<'r>: ExtractRequest<'r>;
+    /// Messages sent by the client.
+    type Incoming: Serialize + DeserializeOwned + Send + 'static;
+    /// Messages sent by the server.
+    type Outgoing: Serialize + DeserializeOwned + Send + 'static;
+
+    /// Codec of the messages, JSON by default.
+    fn codec() -> PropsCodec {
+        PropsCodec::Json
+    }
+
+    /// Function executed for each connection, the connection is closed when it returns.
+    async fn on_connect<'url, 'r>(
+        route: Self::Route<'url>,
+        state: Self::State<'r>,
+        channel: WsChannel<Self::Incoming, Self::Outgoing>,
+    );
+}
+
+/// Connection to a `WebSocketRoute` whose state is already extracted,
+/// run by the adapters on the upgraded connection.
+pub type WsConnect<'a> = Box<dyn FnOnce(WebSocket) -> BoxFuture<'a, ()> + Send + 'a>;
+
+/// Internal trait used to implement the `WebSocketRoute` trait in a dynamic dispatch way.
+/// This trait is NOT meant to be implemented by hand,
+/// it is automaticaly implemented for all types implementing the `WebSocketRoute` trait.
+///
+/// # Safety
+///
+/// Implementors must only downcast the route pointers they receive to the route type
+/// they matched in `DynRoutable::try_match_route`.
+pub unsafe trait DynWebSocketRoute: DynRoutable {
+    /// Extract the state of the route, before the connection is upgraded,
+    /// and return the wrapper for the `WebSocketRoute::on_connect` function.
+    ///
+    /// # Safety
+    ///
+    /// `route_ptr` must have been returned by `try_match_route` on the same route,
+    /// it is downcast to the route type without any check.
+    unsafe fn accept<'url, 'r, 'a>(
+        &self,
+        route_ptr: RouteUntypedPtr<'url>,
+        parts: RequestParts<'r>,
+    ) -> Result<WsConnect<'a>, StonkksError>
+    where
+        'url: 'a,
+        'r: 'a;
+}
+
+unsafe impl<T: WebSocketRoute> DynWebSocketRoute for T {
+    unsafe fn accept<'url, 'r, 'a>(
+        &self,
+        route_ptr: RouteUntypedPtr<'url>,
+        parts: RequestParts<'r>,
+    ) -> Result<WsConnect<'a>, StonkksError>
+    where
+        'url: 'a,
+        'r: 'a,
+    {
+        // trust the caller to pass down a route_ptr of the valid type.
+        let route = *route_ptr.downcast::<T>();
+        let state = <T::State<'r> as ExtractRequest<'r>>::extract(parts)?;
+        Ok(Box::new(move |socket| {
+            let channel = WsChannel::new(socket, <T as WebSocketRoute>::codec());
+            <T as WebSocketRoute>::on_connect(route, state, channel)
+        }))
+    }
+}
@@ -1 +1,345 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Field, Fields, Ident,
+    Lit, Meta, NestedMeta, Result,
+};
 
+/// Derive `Props`, `IntoProps` and the reactive counterpart of the props.
+///
+/// Every field of the reactive props is a `&'a Signal<T>`, unless the field is marked with
+/// `#[props(plain)]`, in which case it is moved as is.
+/// The reactive struct is named by replacing the `Props` suffix of the struct name with
+/// `ReactiveProps` (`CounterProps` -> `CounterReactiveProps`), the name can be set with
+/// `#[props(reactive = "Name")]` on the struct.
+///
+/// With `#[props(snapshot)]` on the struct, the reactive struct also implements `Clone` and
+/// `SnapshotProps`, the fields must then implement `Clone`.
+///
+/// ```
+/// # use serde::{Deserialize, Serialize};
+/// # use stonkks::prelude::*;
+/// #[derive(Serialize, Deserialize, Props)]
+/// pub struct CounterProps {
+///     count: i32,
+///     #[props(plain)]
+///     name: String,
+/// }
+/// ```
+///
+/// Props can't be derived for enums and generic structs, and unknown attributes are rejected:
+///
+/// ```compile_fail
+/// # use serde::{Deserialize, Serialize};
+/// # use stonkks::prelude::*;
+/// #[derive(Serialize, Deserialize, Props)]
+/// pub enum TabProps {
+///     Home,
+///     Settings,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use serde::{Deserialize, Serialize};
+/// # use stonkks::prelude::*;
+/// #[derive(Serialize, Deserialize, Props)]
+/// pub struct ListProps<T> {
+///     items: Vec<T>,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use serde::{Deserialize, Serialize};
+/// # use stonkks::prelude::*;
+/// #[derive(Serialize, Deserialize, Props)]
+/// #[props(reactive = Signals)]
+/// pub struct CounterProps {
+///     count: i32,
+/// }
+/// ```
+///
+/// ```compile_fail
+/// # use serde::{Deserialize, Serialize};
+/// # use stonkks::prelude::*;
+/// #[derive(Serialize, Deserialize, Props)]
+/// pub struct CounterProps {
+///     #[props(skip)]
+///     count: i32,
+/// }
+/// ```
+#[proc_macro_derive(Props, attributes(props))]
+pub fn derive_props(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_props(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_props(input: DeriveInput) -> Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(
+            input.generics.span(),
+            "Props can't be derived for generic structs",
+        ));
+    }
+    let data = match &input.data {
+        Data::Struct(data) => data,
+        _ => {
+            return Err(Error::new(
+                input.ident.span(),
+                "Props can only be derived for structs",
+            ))
+        }
+    };
+
+    let props_name = &input.ident;
+    let options = ContainerOptions::parse(&input)?;
+    let reactive_name = &options.reactive_name;
+    let vis = &input.vis;
+
+    // the lifetime must be used by at least one field.
+    let needs_marker = data
+        .fields
+        .iter()
+        .map(is_plain)
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .all(|plain| plain);
+    let marker = needs_marker.then(|| quote!(::std::marker::PhantomData<&'a ()>));
+    let marker_value = needs_marker.then(|| quote!(::std::marker::PhantomData));
+
+    let (reactive_struct, conversion, snapshot) = match &data.fields {
+        Fields::Named(fields) => {
+            let mut reactive_fields = Vec::new();
+            let mut conversions = Vec::new();
+            let mut snapshots = Vec::new();
+            for field in &fields.named {
+                let field_vis = &field.vis;
+                let name = field.ident.as_ref().unwrap();
+                let ty = reactive_field_type(field)?;
+                let value = reactive_field_value(field, quote!(self.#name))?;
+                let snapshot = snapshot_field_value(field, quote!(self.#name))?;
+                reactive_fields.push(quote!(#field_vis #name: #ty));
+                conversions.push(quote!(#name: #value));
+                snapshots.push(quote!(#name: #snapshot));
+            }
+            let marker = marker.map(|marker| quote!(_marker: #marker));
+            let marker_value = marker_value.map(|value| quote!(_marker: #value));
+            (
+                quote!(#vis struct #reactive_name<'a> { #(#reactive_fields,)* #marker }),
+                quote!(#reactive_name { #(#conversions,)* #marker_value }),
+                quote!(#props_name { #(#snapshots,)* }),
+            )
+        }
+        Fields::Unnamed(fields) => {
+            let mut reactive_fields = Vec::new();
+            let mut conversions = Vec::new();
+            let mut snapshots = Vec::new();
+            for (index, field) in fields.unnamed.iter().enumerate() {
+                let field_vis = &field.vis;
+                let index = syn::Index::from(index);
+                let ty = reactive_field_type(field)?;
+                let value = reactive_field_value(field, quote!(self.#index))?;
+                let snapshot = snapshot_field_value(field, quote!(self.#index))?;
+                reactive_fields.push(quote!(#field_vis #ty));
+                conversions.push(value);
+                snapshots.push(snapshot);
+            }
+            (
+                quote!(#vis struct #reactive_name<'a>(#(#reactive_fields,)* #marker);),
+                quote!(#reactive_name(#(#conversions,)* #marker_value)),
+                quote!(#props_name(#(#snapshots,)*)),
+            )
+        }
+        Fields::Unit => (
+            quote!(#vis struct #reactive_name<'a>(#marker);),
+            quote!(#reactive_name(#marker_value)),
+            quote!(#props_name),
+        ),
+    };
+
+    let snapshot_impl = options.snapshot.then(|| {
+        quote! {
+            impl<'a> ::stonkks::prelude::SnapshotProps<'a> for #reactive_name<'a> {
+                fn snapshot(&self) -> #props_name {
+                    #snapshot
+                }
+            }
+        }
+    });
+    let derive_clone = options.snapshot.then(|| quote!(#[derive(Clone)]));
+
+    Ok(quote! {
+        #derive_clone
+        #reactive_struct
+
+        #snapshot_impl
+
+        impl ::stonkks::prelude::Props for #props_name {}
+
+        impl<'a> ::stonkks::prelude::ReactiveProps<'a> for #reactive_name<'a> {
+            type Props = #props_name;
+        }
+
+        impl ::stonkks::prelude::IntoProps for #props_name {
+            type ReactiveProps<'a> = #reactive_name<'a>;
+
+            #[allow(unused_variables)]
+            fn into_reactive_props<'a>(
+                self,
+                cx: ::stonkks::sycamore::prelude::Scope<'a>,
+            ) -> Self::ReactiveProps<'a> {
+                #conversion
+            }
+        }
+    })
+}
+
+/// Options set with `#[props(..)]` on the struct.
+struct ContainerOptions {
+    /// Name of the generated reactive struct.
+    reactive_name: Ident,
+    /// Whether `SnapshotProps` is implemented.
+    snapshot: bool,
+}
+
+impl ContainerOptions {
+    fn parse(input: &DeriveInput) -> Result<Self> {
+        let mut reactive_name = None;
+        let mut snapshot = false;
+        for meta in props_attributes(&input.attrs)? {
+            match meta {
+                NestedMeta::Meta(Meta::NameValue(name_value))
+                    if name_value.path.is_ident("reactive") =>
+                {
+                    match name_value.lit {
+                        Lit::Str(name) => {
+                            reactive_name = Some(Ident::new(&name.value(), name.span()))
+                        }
+                        lit => return Err(Error::new(lit.span(), "expected a string literal")),
+                    }
+                }
+                NestedMeta::Meta(Meta::Path(path)) if path.is_ident("snapshot") => snapshot = true,
+                meta => return Err(Error::new(meta.span(), "unknown props attribute")),
+            }
+        }
+        let reactive_name = reactive_name.unwrap_or_else(|| default_reactive_name(input));
+        Ok(ContainerOptions {
+            reactive_name,
+            snapshot,
+        })
+    }
+}
+
+fn default_reactive_name(input: &DeriveInput) -> Ident {
+    let name = input.ident.to_string();
+    let name = match name.strip_suffix("Props") {
+        Some(prefix) => format!("{}ReactiveProps", prefix),
+        None => format!("{}ReactiveProps", name),
+    };
+    format_ident!("{}", name, span = Span::call_site())
+}
+
+/// Whether the field is marked with `#[props(plain)]`.
+fn is_plain(field: &Field) -> Result<bool> {
+    let mut plain = false;
+    for meta in props_attributes(&field.attrs)? {
+        match meta {
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("plain") => plain = true,
+            meta => return Err(Error::new(meta.span(), "unknown props attribute")),
+        }
+    }
+    Ok(plain)
+}
+
+fn reactive_field_type(field: &Field) -> Result<TokenStream2> {
+    let ty = &field.ty;
+    if is_plain(field)? {
+        Ok(quote!(#ty))
+    } else {
+        Ok(quote!(&'a ::stonkks::sycamore::prelude::Signal<#ty>))
+    }
+}
+
+fn reactive_field_value(field: &Field, value: TokenStream2) -> Result<TokenStream2> {
+    if is_plain(field)? {
+        Ok(value)
+    } else {
+        Ok(quote!(::stonkks::sycamore::prelude::create_signal(cx, #value)))
+    }
+}
+
+fn snapshot_field_value(field: &Field, value: TokenStream2) -> Result<TokenStream2> {
+    if is_plain(field)? {
+        Ok(quote!(::std::clone::Clone::clone(&#value)))
+    } else {
+        Ok(quote!(::std::clone::Clone::clone(&*#value.get())))
+    }
+}
+
+/// Content of all the `#[props(..)]` attributes.
+fn props_attributes(attrs: &[Attribute]) -> Result<Vec<NestedMeta>> {
+    let mut metas = Vec::new();
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("props")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => metas.extend(list.nested),
+            meta => return Err(Error::new(meta.span(), "expected #[props(..)]")),
+        }
+    }
+    Ok(metas)
+}
+
+/// Derive `DynPage` for a type implementing `LoaderPage`,
+/// the props of the page are loaded by running its loaders.
+///
+/// ```ignore
+/// #[derive(DynPage)]
+/// struct UserPage<S>(PhantomData<S>);
+///
+/// #[async_trait]
+/// impl<S: Send + Sync + 'static> LoaderPage for UserPage<S> {
+///     // ...
+/// }
+/// ```
+#[proc_macro_derive(DynPage)]
+pub fn derive_dyn_page(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_dyn_page(input).into()
+}
+
+fn expand_dyn_page(input: DeriveInput) -> TokenStream2 {
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    generics
+        .make_where_clause()
+        .predicates
+        .push(syn::parse_quote!(Self: ::stonkks::prelude::LoaderPage));
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        #[::stonkks::__private::async_trait]
+        impl #impl_generics ::stonkks::prelude::DynPage for #name #ty_generics #where_clause {
+            type Err<'url> = <Self as ::stonkks::prelude::LoaderPage>::Err<'url>;
+            type State<'r> = ::stonkks::__private::Loaders<
+                'r,
+                <Self as ::stonkks::prelude::LoaderPage>::Loaders,
+            >;
+
+            async fn get_server_props<'url, 'r>(
+                route: Self::Route<'url>,
+                loaders: Self::State<'r>,
+            ) -> ::std::result::Result<Self::Props, Self::Err<'url>> {
+                ::stonkks::__private::get_loader_page_props::<Self>(route, loaders).await
+            }
+
+            fn cache_policy() -> ::std::option::Option<::stonkks::prelude::CachePolicy> {
+                <Self as ::stonkks::prelude::LoaderPage>::cache_policy()
+            }
+
+            fn coalesce_requests() -> bool {
+                <Self as ::stonkks::prelude::LoaderPage>::coalesce_requests()
+            }
+        }
+    }
+}
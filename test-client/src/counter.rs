@@ -4,30 +4,12 @@ use sycamore::prelude::*;
 
 pub struct Counter;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Props)]
+#[props(snapshot)]
 pub struct CounterProps {
     count: i32,
 }
 
-pub struct CounterReactiveProps<'a> {
-    count: &'a Signal<i32>,
-}
-
-impl Props for CounterProps {}
-
-impl<'a> ReactiveProps<'a> for CounterReactiveProps<'a> {
-    type Props = CounterProps;
-}
-
-impl IntoProps for CounterProps {
-    type ReactiveProps<'a> = CounterReactiveProps<'a>;
-
-    fn into_reactive_props<'a>(self, cx: Scope<'a>) -> Self::ReactiveProps<'a> {
-        let count = create_signal(cx, self.count);
-        CounterReactiveProps { count }
-    }
-}
-
 impl Component for Counter {
     type Props = CounterProps;
 
@@ -46,13 +28,18 @@ impl Component for Counter {
         }
     }
 
-    fn render_head<'a, G: Html>(cx: Scope<'a>, props: &Self::Props) -> View<G> {
+    fn head<'a>(cx: Scope<'a>, props: &ComponentReactiveProps<'a, Self>) -> &'a ReadSignal<Head> {
         let count = props.count;
-        view! { cx,
-            title {
-                "counter: " (count)
-            }
-        }
+        create_memo(cx, move || {
+            Head::new().title(format!("counter: {}", count.get()))
+        })
+    }
+
+    fn snapshot_props<'a>(
+        cx: Scope<'a>,
+        props: &ComponentReactiveProps<'a, Self>,
+    ) -> Option<&'a ReadSignal<Self::Props>> {
+        Some(snapshot_signal(cx, props))
     }
 }
 
@@ -17,15 +17,11 @@ impl Component for Index {
         }
     }
 
-    fn render_head<'a, G: Html>(
-        cx: Scope<'a>,
-        _props: &ComponentReactiveProps<'a, Self>,
-    ) -> View<G> {
-        view! { cx,
-            title {
-                "index"
-            }
-        }
+    fn head<'a>(cx: Scope<'a>, _props: &ComponentReactiveProps<'a, Self>) -> &'a ReadSignal<Head> {
+        let head = Head::new()
+            .title("index")
+            .description("Index page of the test client.");
+        create_signal(cx, head)
     }
 }
 
@@ -0,0 +1,278 @@
+//! rocket 0.5.0-rc.2 can't upgrade connections, so this server sits in front of it:
+//! it upgrades the requests of the WebSocket routes and proxies the other ones to rocket.
+
+use std::{convert::Infallible, net::IpAddr, net::SocketAddr, sync::Arc};
+
+use futures::{channel::oneshot, SinkExt, StreamExt};
+use hyper::{
+    client::HttpConnector,
+    header::{self, HeaderValue},
+    server::conn::AddrStream,
+    service::{make_service_fn, service_fn},
+    upgrade::Upgraded,
+    Body, Client, Request, Response, StatusCode,
+};
+use rocket::log::error_;
+use stonkks::prelude::*;
+use stonkks::response::{Response as StonkksResponse, ResponseBody};
+use tokio_tungstenite::{
+    tungstenite::{handshake::derive_accept_key, protocol::Role, Message},
+    WebSocketStream,
+};
+
+pub async fn serve(
+    server: Arc<Server>,
+    addr: SocketAddr,
+    rocket_addr: SocketAddr,
+) -> Result<(), hyper::Error> {
+    let client = Client::new();
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let server = Arc::clone(&server);
+        let client = client.clone();
+        let client_ip = conn.remote_addr().ip();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(
+                    Arc::clone(&server),
+                    client.clone(),
+                    client_ip,
+                    rocket_addr,
+                    request,
+                )
+            }))
+        }
+    });
+    hyper::Server::bind(&addr).serve(make_service).await
+}
+
+async fn handle(
+    server: Arc<Server>,
+    client: Client<HttpConnector>,
+    client_ip: IpAddr,
+    rocket_addr: SocketAddr,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let raw_url = request_url(&request);
+    let url = OwnedUrlInfos::parse_from_url(&raw_url);
+    if server.is_websocket(&url) {
+        if let Some(key) = websocket_key(&request) {
+            return Ok(upgrade(server, client_ip, key, request).await);
+        }
+    }
+    Ok(proxy(client, client_ip, rocket_addr, request).await)
+}
+
+fn request_url(request: &Request<Body>) -> String {
+    request
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/")
+        .to_owned()
+}
+
+/// Key of the WebSocket handshake, `None` if the request is not a valid upgrade.
+fn websocket_key(request: &Request<Body>) -> Option<HeaderValue> {
+    let headers = request.headers();
+    let has_token = |name: header::HeaderName, token: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| {
+                value
+                    .split(',')
+                    .any(|part| part.trim().eq_ignore_ascii_case(token))
+            })
+            .unwrap_or(false)
+    };
+    let is_upgrade = request.method() == hyper::Method::GET
+        && has_token(header::CONNECTION, "upgrade")
+        && has_token(header::UPGRADE, "websocket")
+        && has_token(header::SEC_WEBSOCKET_VERSION, "13");
+    if !is_upgrade {
+        return None;
+    }
+    headers.get(header::SEC_WEBSOCKET_KEY).cloned()
+}
+
+fn convert_headers(request: &Request<Body>) -> Headers {
+    request
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)))
+        .collect()
+}
+
+async fn upgrade(
+    server: Arc<Server>,
+    client_ip: IpAddr,
+    key: HeaderValue,
+    mut request: Request<Body>,
+) -> Response<Body> {
+    // the connection borrows the server and the url, so it lives in its own task
+    // which hands back the response of the handshake before running it.
+    let (sender, receiver) = oneshot::channel();
+    let on_upgrade = hyper::upgrade::on(&mut request);
+    rocket::tokio::spawn(async move {
+        let raw_url = request_url(&request);
+        let url = OwnedUrlInfos::parse_from_url(&raw_url);
+        let headers = convert_headers(&request);
+        let accept = server
+            .accept_websocket(Some(client_ip), &url, &headers)
+            .await;
+        // the middlewares and the state of the route are checked before the upgrade.
+        let upgrade = match accept {
+            Some(Ok(WebSocketAccept::Upgrade(upgrade))) => upgrade,
+            Some(Ok(WebSocketAccept::Respond(response))) => {
+                let _ = sender.send(convert_response(response.into_response()));
+                return;
+            }
+            Some(Err(err)) => {
+                let _ = sender.send(error_response(&server, &url, &headers, &err));
+                return;
+            }
+            None => {
+                let _ = sender.send(empty_response(StatusCode::NOT_FOUND));
+                return;
+            }
+        };
+        let connect = match upgrade.connect() {
+            Ok(connect) => connect,
+            Err(err) => {
+                let _ = sender.send(error_response(&server, &url, &headers, &err));
+                return;
+            }
+        };
+        let mut response = empty_response(StatusCode::SWITCHING_PROTOCOLS);
+        for (name, value) in upgrade.response_headers().iter() {
+            append_header(&mut response, name, value);
+        }
+        let response_headers = response.headers_mut();
+        response_headers.insert(header::CONNECTION, HeaderValue::from_static("Upgrade"));
+        response_headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+        if let Ok(accept_key) = HeaderValue::from_str(&derive_accept_key(key.as_bytes())) {
+            response_headers.insert(header::SEC_WEBSOCKET_ACCEPT, accept_key);
+        }
+        if sender.send(response).is_err() {
+            return;
+        }
+        match on_upgrade.await {
+            Ok(upgraded) => connect(into_socket(upgraded).await).await,
+            Err(err) => error_!("The upgrade of {} failed: {}", url.url(), err),
+        }
+    });
+    receiver
+        .await
+        .unwrap_or_else(|_| empty_response(StatusCode::INTERNAL_SERVER_ERROR))
+}
+
+async fn into_socket(upgraded: Upgraded) -> WebSocket {
+    let stream = WebSocketStream::from_raw_socket(upgraded, Role::Server, None).await;
+    let (sink, stream) = stream.split();
+    // ping and pong messages are answered by tungstenite.
+    let incoming = stream.filter_map(|message| async move {
+        match message {
+            Ok(Message::Text(text)) => Some(Ok(WsMessage::Text(text))),
+            Ok(Message::Binary(bytes)) => Some(Ok(WsMessage::Binary(bytes))),
+            Ok(Message::Close(_)) => Some(Ok(WsMessage::Close)),
+            Ok(_) => None,
+            Err(err) => Some(Err(WsError::Transport(err.to_string()))),
+        }
+    });
+    let outgoing = sink
+        .sink_map_err(|err| WsError::Transport(err.to_string()))
+        .with(|message: WsMessage| async move {
+            Ok::<_, WsError>(match message {
+                WsMessage::Text(text) => Message::Text(text),
+                WsMessage::Binary(bytes) => Message::Binary(bytes),
+                WsMessage::Close => Message::Close(None),
+            })
+        });
+    WebSocket::new(incoming, outgoing)
+}
+
+async fn proxy(
+    client: Client<HttpConnector>,
+    client_ip: IpAddr,
+    rocket_addr: SocketAddr,
+    mut request: Request<Body>,
+) -> Response<Body> {
+    let uri = format!("http://{}{}", rocket_addr, request_url(&request));
+    match uri.parse() {
+        Ok(uri) => *request.uri_mut() = uri,
+        Err(_) => return empty_response(StatusCode::BAD_REQUEST),
+    }
+    // rocket reads the address of the client from this header.
+    let client_ip = HeaderValue::from_str(&client_ip.to_string())
+        .expect("an ip address is a valid header value");
+    request.headers_mut().insert("X-Real-IP", client_ip);
+    match client.request(request).await {
+        Ok(response) => response,
+        Err(err) => {
+            error_!(
+                "An error occured while proxying a request to rocket: {}",
+                err
+            );
+            empty_response(StatusCode::BAD_GATEWAY)
+        }
+    }
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+fn append_header(response: &mut Response<Body>, name: &str, value: &str) {
+    let name = header::HeaderName::from_bytes(name.as_bytes());
+    let value = HeaderValue::from_str(value);
+    if let (Ok(name), Ok(value)) = (name, value) {
+        response.headers_mut().append(name, value);
+    }
+}
+
+fn convert_response(stonkks_response: StonkksResponse) -> Response<Body> {
+    let body = match stonkks_response.content {
+        ResponseBody::Full(content) => Body::from(content),
+        ResponseBody::Stream(stream) => Body::wrap_stream(stream),
+    };
+    let mut response = Response::new(body);
+    *response.status_mut() = StatusCode::from_u16(stonkks_response.status.code())
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    if let Some(content_type) = stonkks_response.content_type {
+        append_header(&mut response, "Content-Type", &content_type.to_string());
+    }
+    for (name, value) in stonkks_response.headers.iter() {
+        append_header(&mut response, name, value);
+    }
+    response
+}
+
+fn error_response(
+    server: &Server,
+    url: &OwnedUrlInfos,
+    headers: &Headers,
+    err: &StonkksError,
+) -> Response<Body> {
+    if err.report().log {
+        error_!("An error occured at {} : {}", url.url(), err);
+    }
+    let ErrorResponse {
+        status,
+        body,
+        headers,
+    } = server.error_response(url, headers, err);
+    let (content_type, body) = match body {
+        ErrorBody::Html(html) => ("text/html; charset=utf-8", html),
+        ErrorBody::Json(json) => ("application/json", json),
+    };
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() =
+        StatusCode::from_u16(status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    append_header(&mut response, "Content-Type", content_type);
+    for (name, value) in headers {
+        append_header(&mut response, name, &value);
+    }
+    response
+}
@@ -1,11 +1,17 @@
+mod front;
 mod routes;
 mod states;
 use routes::counter::CountApi;
+use routes::echo::EchoSocket;
 use states::counter::CounterState;
 
-use std::{ops::Deref, sync::Arc};
+use std::{io::Cursor, net::SocketAddr, ops::Deref, sync::Arc};
+
+use futures::TryStreamExt;
+use tokio_util::io::StreamReader;
 
 use rocket::{
+    data::ToByteUnit,
     fs::{relative, FileServer},
     http::{ContentType as RocketContentType, Method, Status},
     outcome::Outcome,
@@ -13,7 +19,7 @@ use rocket::{
     route::Handler,
     Catcher, Data, Response, Route as RocketRoute,
 };
-use stonkks::prelude::{ServerResponse as StonkksResponse, *};
+use stonkks::prelude::{Method as StonkksMethod, ServerResponse as StonkksResponse, *};
 use test_client::get_app;
 
 use rocket::log::error_;
@@ -41,10 +47,8 @@ impl<'a> Uri<'a> {
 }
 
 fn convert_content_type(content_type: ContentType) -> RocketContentType {
-    match content_type {
-        ContentType::Text => RocketContentType::Text,
-        ContentType::Json => RocketContentType::JSON,
-    }
+    RocketContentType::parse_flexible(&content_type.to_string())
+        .unwrap_or(RocketContentType::Binary)
 }
 
 #[derive(Clone)]
@@ -57,9 +61,38 @@ impl Handler for MyServer {
         request: &'r Request<'_>,
         data: Data<'r>,
     ) -> Outcome<Response<'r>, Status, Data<'r>> {
-        let _test = Test::new();
-        let url = Uri::from_request(request);
-        let result = self.0.respond(&url).await;
+        // keep the query string, unlike `Uri::from_request`.
+        let raw_url = request.uri().to_string();
+        let url = Uri(OwnedUrlInfos::parse_from_url(&raw_url));
+        let headers: Headers = request
+            .headers()
+            .iter()
+            .map(|header| (header.name().to_string(), header.value().to_string()))
+            .collect();
+        let Some(method) = StonkksMethod::from_name(request.method().as_str()) else {
+            return Outcome::Forward(data);
+        };
+        // only read the body of the routes using it, so other requests can be forwarded.
+        let body_limit = self.0.body_limit(method, &url);
+        let (body, data) = if body_limit == 0 {
+            (RequestBody::empty(), Some(data))
+        } else {
+            match data.open((body_limit + 1).bytes()).into_bytes().await {
+                Ok(bytes) => {
+                    let body = RequestBody::new(bytes.into_inner());
+                    let body = match request.content_type() {
+                        Some(content_type) => body.with_content_type(content_type.to_string()),
+                        None => body,
+                    };
+                    (body, None)
+                }
+                Err(_) => return Outcome::Failure(Status::BadRequest),
+            }
+        };
+        let result = self
+            .0
+            .respond_from(request.client_ip(), method, &url, &headers, &body)
+            .await;
         match result {
             Some(Ok(StonkksResponse::Html(html))) => {
                 let response = (RocketContentType::HTML, html).respond_to(request);
@@ -69,33 +102,97 @@ impl Handler for MyServer {
                 }
             }
             Some(Ok(StonkksResponse::Api(api_response))) => {
-                let content_type = convert_content_type(api_response.content_type);
-                let content = api_response.content;
-                let response = (content_type, content).respond_to(request);
+                let response = match api_response.content {
+                    ResponseBody::Full(content) => content.respond_to(request),
+                    ResponseBody::Stream(stream) => {
+                        // a failing chunk aborts the connection, so the client can't
+                        // mistake the truncated body for a complete one.
+                        let chunks = stream
+                            .inspect_err(|err| {
+                                error_!("An error occured while streaming a response: {}", err)
+                            })
+                            .map_ok(Cursor::new);
+                        Response::build()
+                            .streamed_body(StreamReader::new(chunks))
+                            .ok()
+                    }
+                };
                 match response {
-                    Ok(rep) => Outcome::Success(rep),
+                    Ok(mut rep) => {
+                        rep.set_status(Status::new(api_response.status.code()));
+                        match api_response.content_type {
+                            Some(content_type) => {
+                                rep.set_header(convert_content_type(content_type));
+                            }
+                            None => rep.remove_header("Content-Type"),
+                        }
+                        for (name, value) in api_response.headers.iter() {
+                            rep.adjoin_raw_header(name.to_owned(), value.to_owned());
+                        }
+                        Outcome::Success(rep)
+                    }
                     Err(status) => Outcome::Failure(status),
                 }
             }
-            Some(Ok(StonkksResponse::Props(props))) => {
-                let response = (RocketContentType::JSON, props).respond_to(request);
+            Some(Ok(StonkksResponse::Props(props, codec))) => {
+                let content_type = RocketContentType::parse_flexible(codec.content_type())
+                    .unwrap_or(RocketContentType::Plain);
+                let response = (content_type, props).respond_to(request);
                 match response {
                     Ok(rep) => Outcome::Success(rep),
                     Err(status) => Outcome::Failure(status),
                 }
             }
             Some(Err(err)) => {
-                error_!("An error occured at {} : {}", url.url(), err);
-                Outcome::Failure(Status::InternalServerError)
+                if err.report().log {
+                    error_!("An error occured at {} : {}", url.url(), err);
+                }
+                let ErrorResponse {
+                    status,
+                    body,
+                    headers,
+                } = self.0.error_response(&url, &headers, &err);
+                let status = Status::new(status);
+                let response = match body {
+                    ErrorBody::Html(html) => {
+                        (status, (RocketContentType::HTML, html)).respond_to(request)
+                    }
+                    ErrorBody::Json(json) => {
+                        (status, (RocketContentType::JSON, json)).respond_to(request)
+                    }
+                };
+                match response {
+                    Ok(mut rep) => {
+                        for (name, value) in headers {
+                            rep.set_raw_header(name, value);
+                        }
+                        Outcome::Success(rep)
+                    }
+                    Err(status) => Outcome::Failure(status),
+                }
             }
-            None => Outcome::Forward(data),
+            None => match data {
+                Some(data) => Outcome::Forward(data),
+                // the body was read, the request can't be forwarded.
+                None => Outcome::Failure(Status::NotFound),
+            },
         }
     }
 }
 
-impl Into<Vec<RocketRoute>> for MyServer {
-    fn into(self) -> Vec<RocketRoute> {
-        vec![RocketRoute::new(Method::Get, "/<_..>", self)]
+impl From<MyServer> for Vec<RocketRoute> {
+    fn from(server: MyServer) -> Self {
+        [
+            Method::Get,
+            Method::Post,
+            Method::Put,
+            Method::Patch,
+            Method::Delete,
+            Method::Options,
+        ]
+        .into_iter()
+        .map(|method| RocketRoute::new(method, "/<_..>", server.clone()))
+        .collect()
     }
 }
 
@@ -122,19 +219,44 @@ impl rocket::catcher::Handler for NotFound {
     }
 }
 
+/// Address of the server, see `front`.
+const ADDR: ([u8; 4], u16) = ([127, 0, 0, 1], 8000);
+/// Address of rocket, behind the front server.
+const ROCKET_ADDR: ([u8; 4], u16) = ([127, 0, 0, 1], 8001);
+
+// the error type is rocket's.
+#[allow(clippy::result_large_err)]
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
     let state = CounterState::default();
-    let app = get_app().state_unwrap(state).api(CountApi).into_server();
+    let app = get_app()
+        .state_unwrap(state)
+        .api(CountApi)
+        .websocket(EchoSocket)
+        .middleware(Timing)
+        .into_server();
 
     app.generate_static_pages().await.unwrap();
 
     let app = Arc::new(app);
     let server = MyServer(Arc::clone(&app));
 
+    let rocket_addr = SocketAddr::from(ROCKET_ADDR);
+    let front = front::serve(Arc::clone(&app), SocketAddr::from(ADDR), rocket_addr);
+    rocket::tokio::spawn(async move {
+        if let Err(err) = front.await {
+            error_!("The front server failed: {}", err);
+        }
+    });
+
     let not_found = NotFound(app);
     let not_found_catcher = Catcher::new(404, not_found);
-    let _rocket = rocket::build()
+    let config = rocket::Config {
+        address: rocket_addr.ip(),
+        port: rocket_addr.port(),
+        ..rocket::Config::default()
+    };
+    let _rocket = rocket::custom(config)
         .mount("/public", FileServer::from(relative!("static")))
         .mount("/", server)
         .register("/", [not_found_catcher])
@@ -143,17 +265,24 @@ async fn main() -> Result<(), rocket::Error> {
     Ok(())
 }
 
-struct Test(std::time::Instant);
+/// Log the time taken by each request.
+struct Timing;
 
-impl Test {
-    fn new() -> Self {
-        Test(std::time::Instant::now())
-    }
-}
-
-impl Drop for Test {
-    fn drop(&mut self) {
-        let elapsed = self.0.elapsed();
-        println!("ELAPSED: {:?}", elapsed);
+#[async_trait::async_trait]
+impl Middleware for Timing {
+    async fn handle<'a, 'url>(
+        &self,
+        request: &mut MiddlewareRequest<'a, 'url>,
+        next: Next<'_>,
+    ) -> Option<Result<StonkksResponse, StonkksError>> {
+        let start = std::time::Instant::now();
+        let response = next.run(request).await;
+        println!(
+            "{} {} in {:?}",
+            request.method(),
+            request.url_infos().url(),
+            start.elapsed()
+        );
+        response
     }
 }
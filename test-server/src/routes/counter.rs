@@ -18,7 +18,7 @@ impl<'a> Route<'a> for CountRoute<'a> {
     fn try_from_url(url: UrlInfos<'_, 'a>) -> Option<Self> {
         let mut iter = url.segments().iter().cloned();
         match (iter.next(), iter.next()) {
-            (Some(route), Some(name)) if route == "hello" => Some(CountRoute { name }),
+            (Some("hello"), Some(name)) => Some(CountRoute { name }),
             _ => None,
         }
     }
@@ -30,21 +30,46 @@ pub struct CounterResponse<'a> {
     count: usize,
 }
 
+#[derive(Debug)]
+pub struct ForbiddenName<'a>(&'a str);
+
+impl<'a> UserError for ForbiddenName<'a> {
+    fn status(&self) -> u16 {
+        403
+    }
+
+    fn public_message(&self) -> String {
+        format!("Can't count the name {}.", self.0)
+    }
+}
+
 #[async_trait::async_trait]
 impl Api for CountApi {
-    type Err<'a> = &'a str;
+    type Err<'a> = ForbiddenName<'a>;
     type State<'r> = State<&'r CounterState>;
+    type Body<'r> = ();
     type Output<'url> = Json<CounterResponse<'url>>;
+
+    fn methods() -> &'static [Method] {
+        &[Method::Get, Method::Delete]
+    }
+
     async fn respond<'url, 'r>(
+        method: Method,
         route: Self::Route<'url>,
         counter: State<&'r CounterState>,
+        _body: (),
     ) -> Result<Self::Output<'url>, Self::Err<'url>> {
         let CountRoute { name } = route;
         if name == "world" {
-            return Err(name);
+            return Err(ForbiddenName(name));
         }
 
-        let count = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let count = match method {
+            // reset the counter, responding with the last count.
+            Method::Delete => counter.swap(0, std::sync::atomic::Ordering::Relaxed),
+            _ => counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed),
+        };
         let response = Json(CounterResponse { count, name });
         Ok(response)
     }
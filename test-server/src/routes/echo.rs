@@ -0,0 +1,45 @@
+use stonkks::prelude::*;
+
+use crate::states::counter::CounterState;
+
+pub struct EchoSocket;
+
+#[derive(Hash)]
+pub struct EchoRoute;
+
+impl Routable for EchoSocket {
+    type Route<'a> = EchoRoute;
+}
+
+impl<'a> Route<'a> for EchoRoute {
+    fn try_from_url(url: UrlInfos<'_, 'a>) -> Option<Self> {
+        match url.segments() {
+            ["echo"] => Some(EchoRoute),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl WebSocketRoute for EchoSocket {
+    type State<'r> = State<&'r CounterState>;
+    type Incoming = String;
+    type Outgoing = String;
+
+    async fn on_connect<'url, 'r>(
+        _route: EchoRoute,
+        counter: State<&'r CounterState>,
+        mut channel: WsChannel<String, String>,
+    ) {
+        while let Some(Ok(message)) = channel.recv().await {
+            let count = counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            if channel
+                .send(&format!("{} #{}", message, count))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    }
+}
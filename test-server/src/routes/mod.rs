@@ -1 +1,2 @@
 pub mod counter;
+pub mod echo;
@@ -1,8 +1,19 @@
+#![allow(clippy::redundant_guards)]
+
 use async_trait::async_trait;
+use futures::{SinkExt, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use stonkks::prelude::*;
 use stonkks_core::pages::DynBasePage;
 use stonkks_core::pointers::*;
+use stonkks_core::request::ExtractBody;
+use stonkks_core::response::Html as HtmlResponse;
 use sycamore::prelude::*;
 
 struct MyLayout;
@@ -21,6 +32,12 @@ impl Layout for MyLayout {
             }
         }
     }
+
+    fn head() -> Head {
+        Head::new()
+            .title("Layout title")
+            .description("Layout description")
+    }
 }
 
 struct MyDynPage;
@@ -39,26 +56,9 @@ impl<'a> Route<'a> for MyRoute<'a> {
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Props)]
 struct MyProps(String);
 
-struct MyReactiveProps<'a>(&'a Signal<String>);
-
-impl Props for MyProps {}
-
-impl IntoProps for MyProps {
-    type ReactiveProps<'a> = MyReactiveProps<'a>;
-
-    fn into_reactive_props<'a>(self, cx: Scope<'a>) -> Self::ReactiveProps<'a> {
-        let signal = create_signal(cx, self.0);
-        MyReactiveProps(signal)
-    }
-}
-
-impl<'a> ReactiveProps<'a> for MyReactiveProps<'a> {
-    type Props = MyProps;
-}
-
 impl Component for MyDynPage {
     type Props = MyProps;
 
@@ -69,6 +69,13 @@ impl Component for MyDynPage {
             }
         }
     }
+
+    fn head<'a>(cx: Scope<'a>, props: &ComponentReactiveProps<'a, Self>) -> &'a ReadSignal<Head> {
+        let greeting = props.0;
+        create_memo(cx, move || {
+            Head::new().title(format!("Greeting {}", greeting.get()))
+        })
+    }
 }
 
 impl Routable for MyDynPage {
@@ -87,6 +94,339 @@ impl DynPage for MyDynPage {
     }
 }
 
+/// Route of the fixtures declared with `test_route!` and `test_page!`,
+/// matching the path of the fixture.
+struct PathRoute<T>(PhantomData<T>);
+
+trait FixturePath {
+    const PATH: &'static str;
+}
+
+impl<T> Hash for PathRoute<T> {
+    fn hash<H: Hasher>(&self, _state: &mut H) {}
+}
+
+impl<'a, T: FixturePath + Send + 'static> Route<'a> for PathRoute<T> {
+    fn try_from_url(url: UrlInfos<'_, 'a>) -> Option<Self> {
+        let path = T::PATH.split('/').filter(|segment| !segment.is_empty());
+        path.eq(url.segments().iter().copied())
+            .then_some(PathRoute(PhantomData))
+    }
+}
+
+/// Route a fixture, a page or an api, at a fixed path.
+macro_rules! test_route {
+    ($fixture:ident at $path:literal) => {
+        impl FixturePath for $fixture {
+            const PATH: &'static str = $path;
+        }
+
+        impl Routable for $fixture {
+            type Route<'a> = PathRoute<$fixture>;
+        }
+    };
+}
+
+/// Declare a dynamic page fixture, served at a fixed path or matching a route type.
+/// The state and the error default to `()`, the page renders the text of `MyProps`
+/// unless `render` is given.
+///
+/// ```ignore
+/// test_page! {
+///     MyCachedPage at "cached" -> MyProps {
+///         state: &'r AtomicUsize,
+///         load: |_route, counter| Ok(MyProps(counter.load(Ordering::Relaxed).to_string())),
+///         cache_policy: CachePolicy::new(Duration::from_secs(60)),
+///     }
+/// }
+/// ```
+macro_rules! test_page {
+    (@or_unit) => { () };
+    (@or_unit $ty:ty) => { $ty };
+    (@render) => {
+        test_page!(@render |cx, props| view! { cx, p { (props.0.get()) } });
+    };
+    (@render |$cx:ident, $props:ident| $render:expr) => {
+        fn render<'a, G: Html>($cx: Scope<'a>, $props: ComponentReactiveProps<'a, Self>) -> View<G> {
+            $render
+        }
+    };
+    ($page:ident at $path:literal -> $props:ty { $($config:tt)* }) => {
+        test_route!($page at $path);
+        test_page!(@page $page -> $props { $($config)* });
+    };
+    ($page:ident($route:ty) -> $props:ty { $($config:tt)* }) => {
+        impl Routable for $page {
+            type Route<'a> = $route;
+        }
+
+        test_page!(@page $page -> $props { $($config)* });
+    };
+    (
+        @page $page:ident -> $props:ty {
+            $(state: $state:ty,)?
+            $(error: $err:ty,)?
+            load: |$route:pat_param, $states:pat_param| $load:expr,
+            $(render: |$cx:ident, $render_props:ident| $render:expr,)?
+            $(mode: $mode:expr,)?
+            $(cache_policy: $policy:expr,)?
+            $(coalesce_requests: $coalesce:expr,)?
+        }
+    ) => {
+        struct $page;
+
+        impl Component for $page {
+            type Props = $props;
+
+            test_page!(@render $(|$cx, $render_props| $render)?);
+
+            $(
+                fn render_mode() -> RenderMode {
+                    $mode
+                }
+            )?
+        }
+
+        #[async_trait]
+        impl DynPage for $page {
+            type Err<'url> = test_page!(@or_unit $($err)?);
+            type State<'r> = test_page!(@or_unit $($state)?);
+
+            async fn get_server_props<'url, 'r>(
+                $route: Self::Route<'url>,
+                $states: Self::State<'r>,
+            ) -> Result<$props, Self::Err<'url>> {
+                $load
+            }
+
+            $(
+                fn cache_policy() -> Option<CachePolicy> {
+                    Some($policy)
+                }
+            )?
+
+            $(
+                fn coalesce_requests() -> bool {
+                    $coalesce
+                }
+            )?
+        }
+    };
+}
+
+/// Render the page matching the url, as requested without any header.
+async fn render(server: &Server, url: &str) -> String {
+    let url_infos = OwnedUrlInfos::parse_from_url(url);
+    let rendered_html = server
+        .try_render_to_string(url_infos.to_shared())
+        .await
+        .unwrap()
+        .unwrap();
+    println!("{}", rendered_html);
+    rendered_html
+}
+
+test_page! {
+    MyLegalPage at "legal" -> () {
+        load: |_route, _states| Ok(()),
+        render: |cx, _props| view! { cx, p { "Legal notice" } },
+        mode: RenderMode::ServerOnly,
+    }
+}
+
+test_page! {
+    MyDashboardPage at "dashboard" -> () {
+        load: |_route, _states| Ok(()),
+        render: |cx, _props| view! { cx, p { "Dashboard content" } },
+        mode: RenderMode::ClientOnly,
+    }
+}
+
+struct MyIsland;
+
+impl Component for MyIsland {
+    type Props = MyProps;
+
+    fn render<'a, G: Html>(cx: Scope<'a>, props: ComponentReactiveProps<'a, Self>) -> View<G> {
+        view! { cx,
+            button {
+                (props.0.get())
+            }
+        }
+    }
+}
+
+impl Island for MyIsland {}
+
+test_page! {
+    MyIslandsPage at "islands" -> MyProps {
+        load: |_route, _states| Ok(MyProps("page props".into())),
+        render: |cx, _props| {
+            let interactive = island::<MyIsland, G>(cx, MyProps("island content".into()));
+            view! { cx,
+                p { "Static content" }
+                (interactive)
+            }
+        },
+        mode: RenderMode::Islands,
+    }
+}
+
+test_page! {
+    MyCachedPage at "cached" -> MyProps {
+        state: (&'r AtomicUsize, Cookies),
+        load: |_route, (counter, cookies)| {
+            let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+            let session = cookies.get("session").unwrap_or("nobody");
+            Ok(MyProps(format!("loaded {} times for {}", count, session)))
+        },
+        cache_policy: CachePolicy::new(Duration::from_secs(60))
+            .vary_header("Accept-Language")
+            .vary_cookie("session"),
+    }
+}
+
+test_page! {
+    MyCoalescedPage(MyRoute<'a>) -> MyProps {
+        state: &'r Arc<AtomicUsize>,
+        load: |route, counter| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(MyProps(route.0.to_string()))
+        },
+        coalesce_requests: true,
+    }
+}
+
+test_page! {
+    MyGreetingPage at "greeting" -> MyProps {
+        state: (&'r Arc<AtomicUsize>, Cookies),
+        load: |_route, (counter, cookies)| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(MyProps(format!("hello {}", cookies.get("session").unwrap_or("nobody"))))
+        },
+        cache_policy: CachePolicy::new(Duration::from_secs(60)).vary_cookie("session"),
+        coalesce_requests: true,
+    }
+}
+
+struct UpperLoader;
+
+#[async_trait]
+impl Loader for UpperLoader {
+    type Input = str;
+    type Output = String;
+    type Err = ();
+    type State<'r> = ();
+
+    async fn load<'r>(input: &str, _state: ()) -> Result<String, ()> {
+        Ok(input.to_uppercase())
+    }
+}
+
+struct FailingLoader;
+
+#[async_trait]
+impl Loader for FailingLoader {
+    type Input = str;
+    type Output = usize;
+    type Err = &'static str;
+    type State<'r> = ();
+
+    async fn load<'r>(_input: &str, _state: ()) -> Result<usize, &'static str> {
+        Err("unavailable")
+    }
+
+    fn fallback(_err: &&'static str) -> Option<usize> {
+        Some(42)
+    }
+}
+
+struct UnavailableLoader;
+
+#[async_trait]
+impl Loader for UnavailableLoader {
+    type Input = str;
+    type Output = usize;
+    type Err = &'static str;
+    type State<'r> = ();
+
+    async fn load<'r>(_input: &str, _state: ()) -> Result<usize, &'static str> {
+        Err("unavailable")
+    }
+}
+
+struct ForbiddenLoader;
+
+#[async_trait]
+impl Loader for ForbiddenLoader {
+    type Input = str;
+    type Output = usize;
+    type Err = Forbidden;
+    type State<'r> = ();
+
+    async fn load<'r>(_input: &str, _state: ()) -> Result<usize, Forbidden> {
+        Err(Forbidden)
+    }
+}
+
+#[derive(DynPage)]
+struct MyLoadersPage<L>(PhantomData<L>);
+
+impl<L: Send + Sync + 'static> Component for MyLoadersPage<L> {
+    type Props = MyProps;
+
+    fn render<'a, G: Html>(cx: Scope<'a>, props: ComponentReactiveProps<'a, Self>) -> View<G> {
+        view! { cx,
+            p {
+                (props.0.get())
+            }
+        }
+    }
+}
+
+impl<L: Send + Sync + 'static> Routable for MyLoadersPage<L> {
+    type Route<'a> = MyRoute<'a>;
+}
+
+#[async_trait]
+impl<L: Loader<Input = str, Output = usize>> LoaderPage for MyLoadersPage<L> {
+    type Err<'url> = LoaderError;
+    type Loaders = (UpperLoader, L);
+
+    fn loader_input<'a, 'url>(route: &'a MyRoute<'url>) -> &'a str {
+        route.0
+    }
+
+    async fn get_server_props<'url>(
+        _route: MyRoute<'url>,
+        (upper, count): (String, usize),
+    ) -> Result<MyProps, LoaderError> {
+        Ok(MyProps(format!("{} {}", upper, count)))
+    }
+}
+
+#[derive(Debug)]
+struct Forbidden;
+
+impl UserError for Forbidden {
+    fn status(&self) -> u16 {
+        403
+    }
+
+    fn public_message(&self) -> String {
+        "Access denied.".into()
+    }
+}
+
+test_page! {
+    MyForbiddenPage at "forbidden" -> MyProps {
+        error: Forbidden,
+        load: |_route, _states| Err(Forbidden),
+    }
+}
+
 struct MyNotFound;
 
 impl Component for MyNotFound {
@@ -235,3 +575,1498 @@ async fn test_dyn_page_total_render() {
 
     assert!(rendered_html.contains(greeting));
 }
+
+#[tokio::test]
+async fn test_head_merge() {
+    let greeting = "test_greeting";
+    let url = format!("index/{}", greeting);
+
+    let app = App::new().dyn_page(MyDynPage).with_layout(MyLayout);
+
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url(&url);
+
+    let rendered_html = server
+        .try_render_to_string(url_infos.to_shared())
+        .await
+        .unwrap()
+        .unwrap();
+
+    println!("{}", rendered_html);
+
+    assert_eq!(rendered_html.matches("</title>").count(), 1);
+    assert!(rendered_html.contains("Greeting test_greeting"));
+    assert!(!rendered_html.contains("Layout title"));
+    assert!(rendered_html.contains("Layout description"));
+}
+
+#[tokio::test]
+async fn test_server_only_page() {
+    let app = App::new().dyn_page(MyDynPage).dyn_page(MyLegalPage);
+    let server = app.into_server();
+
+    let rendered_html = render(&server, "/legal").await;
+
+    assert!(rendered_html.contains("Legal notice"));
+    assert!(!rendered_html.contains(".wasm"));
+    assert!(!rendered_html.contains("type=\"module\""));
+}
+
+#[tokio::test]
+async fn test_client_only_page() {
+    let app = App::new().dyn_page(MyDashboardPage).with_layout(MyLayout);
+    let server = app.into_server();
+
+    let rendered_html = render(&server, "/dashboard").await;
+
+    // the shell is the layout, without the page.
+    let (_, body) = rendered_html.split_once("<body").unwrap();
+    assert!(body.contains("This is a Title"));
+    assert!(body.contains("test paragraphe"));
+    assert!(!rendered_html.contains("Dashboard content"));
+    assert!(rendered_html.contains("type=\"module\""));
+}
+
+#[tokio::test]
+async fn test_islands_page() {
+    let app = App::new().dyn_page(MyIslandsPage).island(MyIsland);
+    let server = app.into_server();
+
+    let rendered_html = render(&server, "/islands").await;
+
+    assert!(rendered_html.contains("Static content"));
+    assert!(rendered_html.contains("island content"));
+    assert!(rendered_html.contains("data-stonkks-island"));
+    assert!(rendered_html.contains("data-stonkks-props"));
+    // only the islands props are sent to the client.
+    assert!(!rendered_html.contains("page props"));
+    // only the islands are hydrated, the page has no hydration keys.
+    let (page, island) = rendered_html.split_once("data-stonkks-island").unwrap();
+    assert!(!page.contains("data-hk"));
+    assert!(island.contains("data-hk"));
+}
+
+#[tokio::test]
+async fn test_page_cache() {
+    let app = App::new()
+        .dyn_page(MyCachedPage)
+        .state_unwrap(AtomicUsize::new(0));
+    let server = app.into_server();
+
+    for _ in 0..3 {
+        assert!(render(&server, "/cached").await.contains("loaded 1 times"));
+    }
+
+    server.invalidate_url("/cached");
+
+    assert!(render(&server, "/cached").await.contains("loaded 2 times"));
+}
+
+#[tokio::test]
+async fn test_page_cache_vary() {
+    let app = App::new()
+        .dyn_page(MyCachedPage)
+        .state_unwrap(AtomicUsize::new(0));
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/cached");
+    let render = |headers: Headers| {
+        let server = &server;
+        let url_infos = &url_infos;
+        async move {
+            match server
+                .respond(Method::Get, url_infos, &headers, &RequestBody::empty())
+                .await
+            {
+                Some(Ok(ServerResponse::Html(html))) => html,
+                _ => panic!("expected the html of the page"),
+            }
+        }
+    };
+    let session =
+        |id: &str| Headers::from_iter([("Cookie", format!("session={}; theme=dark", id))]);
+
+    assert!(render(session("alice"))
+        .await
+        .contains("loaded 1 times for alice"));
+    assert!(render(session("bob"))
+        .await
+        .contains("loaded 2 times for bob"));
+    assert!(render(session("alice"))
+        .await
+        .contains("loaded 1 times for alice"));
+
+    // other cookies are not part of the key.
+    let other_theme = Headers::from_iter([("Cookie", "theme=light; session=bob")]);
+    assert!(render(other_theme).await.contains("loaded 2 times for bob"));
+
+    let french = Headers::from_iter([("Cookie", "session=bob"), ("Accept-Language", "fr")]);
+    assert!(render(french).await.contains("loaded 3 times for bob"));
+}
+
+#[tokio::test]
+async fn test_request_coalescing_vary() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let app = App::new()
+        .dyn_page(MyGreetingPage)
+        .state_unwrap(Arc::clone(&counter));
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/greeting");
+    let render = |session: &str| {
+        let server = &server;
+        let url_infos = &url_infos;
+        let headers = Headers::from_iter([("Cookie", format!("session={}", session))]);
+        async move {
+            match server
+                .respond(Method::Get, url_infos, &headers, &RequestBody::empty())
+                .await
+            {
+                Some(Ok(ServerResponse::Html(html))) => html,
+                _ => panic!("expected the html of the page"),
+            }
+        }
+    };
+
+    let (alice, bob, alice_again) = tokio::join!(render("alice"), render("bob"), render("alice"));
+
+    // requests of different users are not coalesced together.
+    assert!(alice.contains("hello alice"));
+    assert!(bob.contains("hello bob"));
+    assert!(alice_again.contains("hello alice"));
+    assert_eq!(counter.load(Ordering::Relaxed), 2);
+}
+
+#[tokio::test]
+async fn test_request_coalescing() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let app = App::new()
+        .dyn_page(MyCoalescedPage)
+        .state_unwrap(Arc::clone(&counter));
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/index/coalesced");
+
+    let (first, second) = tokio::join!(
+        server.try_render_to_string(url_infos.to_shared()),
+        server.try_render_to_string(url_infos.to_shared()),
+    );
+
+    assert!(first.unwrap().unwrap().contains("coalesced"));
+    assert!(second.unwrap().unwrap().contains("coalesced"));
+    assert_eq!(counter.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn test_request_coalescing_props_and_page() {
+    let counter = Arc::new(AtomicUsize::new(0));
+    let app = App::new()
+        .dyn_page(MyCoalescedPage)
+        .state_unwrap(Arc::clone(&counter));
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/index/coalesced");
+    let props_url_infos = OwnedUrlInfos::parse_from_url("/props/index/coalesced");
+    let headers = Headers::new();
+    let body = RequestBody::empty();
+
+    let (props, html) = tokio::join!(
+        server.respond(Method::Get, &props_url_infos, &headers, &body),
+        server.try_render_to_string(url_infos.to_shared()),
+    );
+
+    assert!(matches!(props, Some(Ok(ServerResponse::Props(..)))));
+    assert!(html.unwrap().unwrap().contains("coalesced"));
+    // the page is rendered from the props loaded for the props request.
+    assert_eq!(counter.load(Ordering::Relaxed), 1);
+}
+
+#[tokio::test]
+async fn test_loaders() {
+    let app = App::new().dyn_page(MyLoadersPage::<FailingLoader>(PhantomData));
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/index/loaded");
+
+    let rendered_html = server
+        .try_render_to_string(url_infos.to_shared())
+        .await
+        .unwrap()
+        .unwrap();
+
+    println!("{}", rendered_html);
+
+    // the failing loader is replaced by its fallback.
+    assert!(rendered_html.contains("LOADED 42"));
+}
+
+#[tokio::test]
+async fn test_failing_loader() {
+    let app = App::new().dyn_page(MyLoadersPage::<UnavailableLoader>(PhantomData));
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/index/loaded");
+
+    let error = match server.try_render_to_string(url_infos.to_shared()).await {
+        Some(Err(error)) => error,
+        _ => panic!("expected an error"),
+    };
+    let report = match &error {
+        StonkksError::User(report) => report,
+        _ => panic!("expected a user error"),
+    };
+
+    assert_eq!(report.status, 500);
+    assert!(report.log);
+    assert!(report.details.contains("UnavailableLoader"));
+    assert!(report.details.contains("unavailable"));
+}
+
+#[tokio::test]
+async fn test_failing_loader_error() {
+    let app = App::new().dyn_page(MyLoadersPage::<ForbiddenLoader>(PhantomData));
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/index/loaded");
+
+    let error = match server.try_render_to_string(url_infos.to_shared()).await {
+        Some(Err(error)) => error,
+        _ => panic!("expected an error"),
+    };
+
+    // the response is the one of the error of the loader.
+    assert_eq!(error.status(), 403);
+    assert_eq!(error.report().message, "Access denied.");
+    assert!(!error.report().log);
+}
+
+#[tokio::test]
+async fn test_page_error() {
+    let app = App::new().dyn_page(MyForbiddenPage);
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/forbidden");
+
+    let error = match server
+        .respond(
+            Method::Get,
+            &url_infos,
+            &Headers::new(),
+            &RequestBody::empty(),
+        )
+        .await
+    {
+        Some(Err(error)) => error,
+        _ => panic!("expected an error"),
+    };
+    let report = match &error {
+        StonkksError::User(report) => report,
+        _ => panic!("expected a user error"),
+    };
+
+    assert_eq!(report.status, 403);
+    assert_eq!(report.message, "Access denied.");
+    assert!(!report.log);
+
+    let ErrorResponse { status, body, .. } =
+        server.error_response(&url_infos, &Headers::new(), &error);
+    assert_eq!(status, 403);
+    match body {
+        ErrorBody::Html(html) => assert!(html.contains("Access denied.")),
+        ErrorBody::Json(_) => panic!("expected the error page"),
+    }
+
+    let props_url_infos = OwnedUrlInfos::parse_from_url("/props/forbidden");
+    let ErrorResponse { body, .. } =
+        server.error_response(&props_url_infos, &Headers::new(), &error);
+    assert!(matches!(body, ErrorBody::Json(json) if json.contains("403")));
+}
+
+#[tokio::test]
+async fn test_missing_state() {
+    let app = App::new().dyn_page(MyCachedPage);
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/cached");
+
+    let error = server
+        .try_render_to_string(url_infos.to_shared())
+        .await
+        .unwrap()
+        .unwrap_err();
+
+    assert!(matches!(error, StonkksError::MissingState(_)));
+    assert_eq!(error.status(), 500);
+    assert_eq!(error.report().message, "Internal Server Error");
+}
+
+#[derive(Serialize, Deserialize, Props)]
+#[props(reactive = "ProfileSignals")]
+struct ProfileProps {
+    name: String,
+    #[props(plain)]
+    id: u32,
+}
+
+#[test]
+fn test_derive_props() {
+    create_scope_immediate(|cx| {
+        let props = ProfileProps {
+            name: "stonkks".into(),
+            id: 7,
+        };
+        let ProfileSignals { name, id } = props.into_reactive_props(cx);
+        assert_eq!(*name.get(), "stonkks");
+        assert_eq!(id, 7);
+        name.set("updated".into());
+        assert_eq!(*name.get(), "updated");
+    });
+}
+
+#[derive(Serialize, Deserialize, Props)]
+#[props(snapshot)]
+struct CartProps {
+    items: Vec<String>,
+    #[props(plain)]
+    owner: String,
+}
+
+#[test]
+fn test_snapshot_props() {
+    create_scope_immediate(|cx| {
+        let props = CartProps {
+            items: vec!["apple".into()],
+            owner: "stonkks".into(),
+        };
+        let reactive_props = props.into_reactive_props(cx);
+        let snapshot = snapshot_signal(cx, &reactive_props);
+        assert_eq!(snapshot.get().items, ["apple"]);
+
+        reactive_props.items.modify().push("pear".into());
+        let CartProps { items, owner } = reactive_props.snapshot();
+        assert_eq!(items, ["apple", "pear"]);
+        assert_eq!(owner, "stonkks");
+        assert_eq!(snapshot.get().items, ["apple", "pear"]);
+    });
+}
+
+#[derive(Serialize, Deserialize, Props)]
+struct ScoreProps(u32, #[props(plain)] String);
+
+#[derive(Serialize, Deserialize, Props)]
+struct SettingsProps {
+    #[props(plain)]
+    theme: String,
+}
+
+#[derive(Serialize, Deserialize, Props)]
+struct EmptyProps;
+
+#[test]
+fn test_derive_props_shapes() {
+    create_scope_immediate(|cx| {
+        let ScoreReactiveProps(score, player) =
+            ScoreProps(3, "stonkks".into()).into_reactive_props(cx);
+        assert_eq!(*score.get(), 3);
+        assert_eq!(player, "stonkks");
+
+        // only plain fields, the lifetime is held by a marker.
+        let SettingsReactiveProps { theme, .. } = SettingsProps {
+            theme: "dark".into(),
+        }
+        .into_reactive_props(cx);
+        assert_eq!(theme, "dark");
+
+        let EmptyReactiveProps(_) = EmptyProps.into_reactive_props(cx);
+    });
+}
+
+#[tokio::test]
+async fn test_props_codec() {
+    let app = App::new().dyn_page(MyDynPage);
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/props/index/codec");
+
+    match server
+        .respond(
+            Method::Get,
+            &url_infos,
+            &Headers::new(),
+            &RequestBody::empty(),
+        )
+        .await
+    {
+        Some(Ok(ServerResponse::Props(props, codec))) => {
+            assert_eq!(codec, PropsCodec::Json);
+            let props: MyProps = codec.decode(&props).unwrap();
+            assert_eq!(props.0, "codec");
+        }
+        _ => panic!("expected props"),
+    }
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/index/codec");
+    let rendered_html = server
+        .try_render_to_string(url_infos.to_shared())
+        .await
+        .unwrap()
+        .unwrap();
+
+    assert!(rendered_html.contains("data-stonkks-codec=\"json\""));
+}
+
+#[cfg(feature = "msgpack")]
+#[tokio::test]
+async fn test_msgpack_props_codec() {
+    let app = App::new()
+        .dyn_page(MyDynPage)
+        .props_codec(PropsCodec::MessagePack);
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/props/index/codec");
+
+    match server
+        .respond(
+            Method::Get,
+            &url_infos,
+            &Headers::new(),
+            &RequestBody::empty(),
+        )
+        .await
+    {
+        Some(Ok(ServerResponse::Props(props, codec))) => {
+            assert_eq!(codec, PropsCodec::MessagePack);
+            assert!(!props.contains('"'));
+            let props: MyProps = codec.decode(&props).unwrap();
+            assert_eq!(props.0, "codec");
+        }
+        _ => panic!("expected props"),
+    }
+}
+
+/// Extract the content of the props script of the page.
+fn embedded_props(html: &str) -> &str {
+    let start = html.find("id=\"__STONKKS_PROPS__\"").unwrap();
+    let start = start + html[start..].find('>').unwrap() + 1;
+    let end = start + html[start..].find("</script>").unwrap();
+    &html[start..end]
+}
+
+const HOSTILE_PROPS: &str = "it's a \\ back\nslash </script><script>alert(1)</script> <!-- &amp;";
+
+test_page! {
+    MyHostilePage at "hostile" -> MyProps {
+        load: |_route, _states| Ok(MyProps(HOSTILE_PROPS.into())),
+    }
+}
+
+#[tokio::test]
+async fn test_props_embedding() {
+    let app = App::new().dyn_page(MyHostilePage);
+    let server = app.into_server();
+
+    let rendered_html = render(&server, "/hostile").await;
+
+    assert!(!rendered_html.contains("<script>alert(1)"));
+    let embedded = embedded_props(&rendered_html);
+    assert!(!embedded.contains('<'));
+    assert!(!embedded.contains('>'));
+    let decoded: MyProps = PropsCodec::Json.decode(embedded).unwrap();
+    assert_eq!(decoded.0, HOSTILE_PROPS);
+}
+
+#[tokio::test]
+async fn test_version_handshake() {
+    let app = App::new().dyn_page(MyDynPage).version("v2");
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/index/version");
+    let rendered_html = server
+        .try_render_to_string(url_infos.to_shared())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(rendered_html.contains("data-stonkks-version=\"v2\""));
+}
+
+#[test]
+fn test_default_version() {
+    let first = App::new()
+        .dyn_page(MyDynPage)
+        .dyn_page(MyLegalPage)
+        .into_server();
+    let second = App::new()
+        .dyn_page(MyLegalPage)
+        .dyn_page(MyDynPage)
+        .into_server();
+    let other = App::new().dyn_page(MyDynPage).into_server();
+
+    // the order of the pages does not matter, their props do.
+    assert_eq!(first.version(), second.version());
+    assert_ne!(first.version(), other.version());
+}
+
+#[derive(Serialize, Deserialize, Props)]
+struct ArticleProps {
+    title: String,
+    body: ServerOnly<String>,
+}
+
+test_page! {
+    MyArticlePage at "article" -> ArticleProps {
+        load: |_route, _states| {
+            // let concurrent requests join the load.
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(ArticleProps {
+                title: "Server only".into(),
+                body: ServerOnly::new("A very long article body".into()),
+            })
+        },
+        render: |cx, props| {
+            let title = props.title;
+            let body = props.body.get().render(cx, "body", |cx, body| {
+                let body = body.clone();
+                view! { cx,
+                    article { (body) }
+                }
+            });
+            view! { cx,
+                h1 { (title.get()) }
+                (body)
+            }
+        },
+        coalesce_requests: true,
+    }
+}
+
+#[tokio::test]
+async fn test_server_only_props() {
+    let app = App::new().dyn_page(MyArticlePage);
+    let server = app.into_server();
+
+    let rendered_html = render(&server, "/article").await;
+
+    assert!(rendered_html.contains("A very long article body"));
+    assert!(rendered_html.contains("data-stonkks-server-only=\"body\""));
+    let embedded = embedded_props(&rendered_html);
+    assert!(embedded.contains("Server only"));
+    assert!(!embedded.contains("A very long article body"));
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/props/article");
+    match server
+        .respond(
+            Method::Get,
+            &url_infos,
+            &Headers::new(),
+            &RequestBody::empty(),
+        )
+        .await
+    {
+        Some(Ok(ServerResponse::Props(props, codec))) => {
+            assert!(!props.contains("A very long article body"));
+            let props: ArticleProps = codec.decode(&props).unwrap();
+            assert!(!props.body.is_available());
+        }
+        _ => panic!("expected props"),
+    }
+}
+
+#[tokio::test]
+async fn test_coalesced_server_only_props() {
+    let app = App::new().dyn_page(MyArticlePage);
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/article");
+    let props_url_infos = OwnedUrlInfos::parse_from_url("/props/article");
+    let headers = Headers::new();
+    let body = RequestBody::empty();
+
+    // a props request leads, the pages render the article from the props it loaded.
+    let (props, first, second) = tokio::join!(
+        server.respond(Method::Get, &props_url_infos, &headers, &body),
+        server.try_render_to_string(url_infos.to_shared()),
+        server.try_render_to_string(url_infos.to_shared()),
+    );
+
+    assert!(matches!(props, Some(Ok(ServerResponse::Props(..)))));
+    for html in [first, second] {
+        assert!(html.unwrap().unwrap().contains("A very long article body"));
+    }
+
+    let (first, second) = tokio::join!(
+        server.try_render_to_string(url_infos.to_shared()),
+        server.try_render_to_string(url_infos.to_shared()),
+    );
+    for html in [first, second] {
+        assert!(html.unwrap().unwrap().contains("A very long article body"));
+    }
+}
+
+struct MyItemsApi;
+
+test_route!(MyItemsApi at "items");
+
+#[async_trait]
+impl Api for MyItemsApi {
+    type Err<'url> = ();
+    type State<'r> = ();
+    type Body<'r> = ();
+    type Output<'url> = Json<&'static str>;
+
+    fn methods() -> &'static [Method] {
+        &[Method::Get, Method::Post]
+    }
+
+    async fn respond<'url, 'r>(
+        method: Method,
+        _route: PathRoute<Self>,
+        _states: (),
+        _body: (),
+    ) -> Result<Json<&'static str>, ()> {
+        match method {
+            Method::Post => Ok(Json("created")),
+            _ => Ok(Json("listed")),
+        }
+    }
+}
+
+struct MyOptionsApi;
+
+test_route!(MyOptionsApi at "options");
+
+#[async_trait]
+impl Api for MyOptionsApi {
+    type Err<'url> = ();
+    type State<'r> = ();
+    type Body<'r> = ();
+    type Output<'url> = Json<&'static str>;
+
+    fn methods() -> &'static [Method] {
+        &[Method::Get, Method::Options]
+    }
+
+    async fn respond<'url, 'r>(
+        _method: Method,
+        _route: PathRoute<Self>,
+        _state: (),
+        _body: (),
+    ) -> Result<Json<&'static str>, ()> {
+        Ok(Json("options"))
+    }
+}
+
+#[test]
+#[should_panic(expected = "OPTIONS")]
+fn test_api_options_rejected() {
+    let _app = App::new().api(MyOptionsApi);
+}
+
+#[tokio::test]
+async fn test_api_methods() {
+    let app = App::new().api(MyItemsApi);
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/api/items");
+
+    for (method, expected) in [(Method::Get, "\"listed\""), (Method::Post, "\"created\"")] {
+        match server
+            .respond(method, &url_infos, &Headers::new(), &RequestBody::empty())
+            .await
+        {
+            Some(Ok(ServerResponse::Api(response))) => {
+                assert_eq!(response.content.as_bytes(), Some(expected.as_bytes()))
+            }
+            _ => panic!("expected an api response"),
+        }
+    }
+
+    let error = match server
+        .respond(
+            Method::Delete,
+            &url_infos,
+            &Headers::new(),
+            &RequestBody::empty(),
+        )
+        .await
+    {
+        Some(Err(error)) => error,
+        _ => panic!("expected a method mismatch"),
+    };
+    assert_eq!(error.status(), 405);
+    let ErrorResponse {
+        status, headers, ..
+    } = server.error_response(&url_infos, &Headers::new(), &error);
+    assert_eq!(status, 405);
+    assert_eq!(headers, [("Allow", "GET, HEAD, POST".to_owned())]);
+
+    // HEAD is served as GET, without the body.
+    match server
+        .respond(
+            Method::Head,
+            &url_infos,
+            &Headers::new(),
+            &RequestBody::empty(),
+        )
+        .await
+    {
+        Some(Ok(ServerResponse::Api(response))) => {
+            assert_eq!(response.status, Status::OK);
+            assert!(response.content_type.is_some());
+            assert_eq!(response.content.as_bytes(), Some(&[][..]));
+        }
+        _ => panic!("expected an api response"),
+    }
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/api/unknown");
+    assert!(server
+        .respond(
+            Method::Delete,
+            &url_infos,
+            &Headers::new(),
+            &RequestBody::empty()
+        )
+        .await
+        .is_none());
+}
+
+struct MyNewItemApi;
+
+#[derive(Deserialize)]
+struct NewItem {
+    name: String,
+}
+
+impl Routable for MyNewItemApi {
+    type Route<'a> = PathRoute<MyItemsApi>;
+}
+
+#[async_trait]
+impl Api for MyNewItemApi {
+    type Err<'url> = ();
+    type State<'r> = ();
+    type Body<'r> = Json<NewItem>;
+    type Output<'url> = Json<String>;
+
+    fn methods() -> &'static [Method] {
+        &[Method::Put]
+    }
+
+    fn body_limit() -> Option<usize> {
+        Some(32)
+    }
+
+    async fn respond<'url, 'r>(
+        _method: Method,
+        _route: PathRoute<MyItemsApi>,
+        _states: (),
+        body: Json<NewItem>,
+    ) -> Result<Json<String>, ()> {
+        Ok(Json(body.0.name))
+    }
+}
+
+#[tokio::test]
+async fn test_api_body() {
+    let app = App::new().api(MyNewItemApi);
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/api/items");
+    assert_eq!(server.body_limit(Method::Put, &url_infos), 32);
+
+    let body =
+        RequestBody::new(r#"{"name":"pear"}"#).with_content_type("application/json; charset=utf-8");
+    match server
+        .respond(Method::Put, &url_infos, &Headers::new(), &body)
+        .await
+    {
+        Some(Ok(ServerResponse::Api(response))) => {
+            assert_eq!(response.content.as_bytes().unwrap(), b"\"pear\"")
+        }
+        _ => panic!("expected an api response"),
+    }
+
+    let rejected = [
+        (RequestBody::new(r#"{"name":"pear"}"#), 415),
+        (
+            RequestBody::new(r#"{"name":"pear""#).with_content_type("application/json"),
+            400,
+        ),
+        (
+            RequestBody::new(format!(r#"{{"name":"{}"}}"#, "a".repeat(32)))
+                .with_content_type("application/json"),
+            413,
+        ),
+    ];
+    for (body, status) in rejected {
+        match server
+            .respond(Method::Put, &url_infos, &Headers::new(), &body)
+            .await
+        {
+            Some(Err(error)) => assert_eq!(error.status(), status),
+            _ => panic!("expected the body to be rejected"),
+        }
+    }
+}
+
+#[test]
+fn test_form_body() {
+    let body = RequestBody::new("name=pear").with_content_type("application/x-www-form-urlencoded");
+    let Form(item) = <Form<NewItem> as ExtractBody>::extract(&body).unwrap();
+    assert_eq!(item.name, "pear");
+
+    let rejection = <Form<NewItem> as ExtractBody>::extract(&RequestBody::new("name=pear"));
+    assert!(matches!(
+        rejection,
+        Err(BodyRejection::UnsupportedMediaType { .. })
+    ));
+}
+
+struct MySearchApi;
+
+test_route!(MySearchApi at "search");
+
+#[derive(Deserialize)]
+struct SearchFilter {
+    name: String,
+    limit: Option<usize>,
+}
+
+#[async_trait]
+impl Api for MySearchApi {
+    type Err<'url> = RequestRejection;
+    type State<'r> = (&'r AtomicUsize, Headers, Query<SearchFilter>, Cookies);
+    type Body<'r> = ();
+    type Output<'url> = Json<String>;
+
+    async fn respond<'url, 'r>(
+        _method: Method,
+        _route: PathRoute<Self>,
+        extracted: Self::State<'r>,
+        _body: (),
+    ) -> Result<Json<String>, RequestRejection> {
+        let (counter, headers, Query(filter), cookies) = extracted;
+        let token = headers.require("Authorization")?;
+        let theme = cookies.require("theme")?;
+        Ok(Json(format!(
+            "{} {} {} {} {}",
+            counter.load(Ordering::Relaxed),
+            token,
+            theme,
+            filter.name,
+            filter.limit.unwrap_or_default()
+        )))
+    }
+}
+
+#[tokio::test]
+async fn test_request_extractors() {
+    let app = App::new()
+        .api(MySearchApi)
+        .state_unwrap(AtomicUsize::new(3));
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/api/search?name=pear&limit=2");
+    let headers: Headers = [
+        ("authorization", "token"),
+        ("Cookie", "lang=en; theme=\"dark\""),
+    ]
+    .into_iter()
+    .collect();
+    match server
+        .respond(Method::Get, &url_infos, &headers, &RequestBody::empty())
+        .await
+    {
+        Some(Ok(ServerResponse::Api(response))) => {
+            assert_eq!(
+                response.content.as_bytes().unwrap(),
+                b"\"3 token dark pear 2\""
+            )
+        }
+        _ => panic!("expected an api response"),
+    }
+
+    let missing_header: Headers = [("Cookie", "theme=dark")].into_iter().collect();
+    let error = match server
+        .respond(
+            Method::Get,
+            &url_infos,
+            &missing_header,
+            &RequestBody::empty(),
+        )
+        .await
+    {
+        Some(Err(error)) => error,
+        _ => panic!("expected a missing header"),
+    };
+    assert_eq!(error.status(), 400);
+    assert_eq!(error.report().message, "Missing header Authorization.");
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/api/search?limit=many");
+    let error = match server
+        .respond(Method::Get, &url_infos, &headers, &RequestBody::empty())
+        .await
+    {
+        Some(Err(error)) => error,
+        _ => panic!("expected an invalid query"),
+    };
+    assert_eq!(error.status(), 400);
+    assert!(error.report().message.starts_with("Invalid query string"));
+}
+
+#[test]
+fn test_into_response() {
+    let response = "hello".into_response().unwrap();
+    assert_eq!(response.status, Status::OK);
+    assert_eq!(response.content_type, Some(ContentType::TEXT));
+
+    let response = ().into_response().unwrap();
+    assert_eq!(response.status, Status::NO_CONTENT);
+    assert!(response.content_type.is_none());
+    assert_eq!(response.content.as_bytes(), Some(&[][..]));
+
+    let response = None::<String>.into_response().unwrap();
+    assert_eq!(response.status, Status::NOT_FOUND);
+
+    let response = vec![1u8, 2, 3].into_response().unwrap();
+    assert_eq!(response.content_type, Some(ContentType::OCTET_STREAM));
+    assert_eq!(response.content.as_bytes().unwrap(), [1, 2, 3]);
+
+    let headers: Headers = [("Location", "/api/items/1")].into_iter().collect();
+    let response = (Status::CREATED, headers, Json(1)).into_response().unwrap();
+    assert_eq!(response.status, Status::CREATED);
+    assert_eq!(response.headers.get("location"), Some("/api/items/1"));
+    assert_eq!(response.content.as_bytes().unwrap(), b"1");
+
+    let result: Result<String, (Status, &'static str)> = Err((Status::CONFLICT, "taken"));
+    let response = result.into_response().unwrap();
+    assert_eq!(response.status, Status::CONFLICT);
+    assert_eq!(response.content.as_bytes().unwrap(), b"taken");
+}
+
+#[test]
+fn test_response_content_types() {
+    let response = HtmlResponse("<li>pear</li>").into_response().unwrap();
+    let content_type = response.content_type.unwrap();
+    assert_eq!(content_type.to_string(), "text/html; charset=utf-8");
+
+    let xlsx = ContentType::new("application/vnd.ms-excel");
+    assert_eq!(xlsx.to_string(), "application/vnd.ms-excel");
+    let latin = ContentType::CSV.with_charset("iso-8859-1");
+    assert_eq!(latin.charset(), Some("iso-8859-1"));
+
+    let csv = Bytes::new("name\npear\n").with_content_type(ContentType::CSV);
+    let response = Attachment::new("export.csv", csv).into_response().unwrap();
+    assert_eq!(response.content_type, Some(ContentType::CSV));
+    assert_eq!(
+        response.headers.get("Content-Disposition"),
+        Some("attachment; filename=\"export.csv\"")
+    );
+
+    let response = Bytes::new(vec![0x89, b'P', b'N', b'G'])
+        .with_content_type(ContentType::PNG)
+        .into_response()
+        .unwrap()
+        .attachment("rapport \"été\".png");
+    assert_eq!(
+        response.headers.get("content-disposition"),
+        Some("attachment; filename=\"rapport __t__.png\"; filename*=UTF-8''rapport%20%22%C3%A9t%C3%A9%22.png")
+    );
+}
+
+#[tokio::test]
+async fn test_streaming_response() {
+    let rows = futures::stream::iter(["name\n", "pear\n"]).map(Ok::<_, std::io::Error>);
+    let response = Streaming::new(rows)
+        .with_content_type(ContentType::CSV)
+        .into_response()
+        .unwrap();
+    assert!(response.content.is_stream());
+    let chunks: Vec<_> = response.content.into_stream().collect().await;
+    let chunks: Vec<_> = chunks.into_iter().map(Result::unwrap).collect();
+    assert_eq!(chunks, [b"name\n".to_vec(), b"pear\n".to_vec()]);
+}
+
+#[tokio::test]
+async fn test_sse_response() {
+    let events = futures::stream::iter([
+        SseEvent::new()
+            .event("tick")
+            .id("1")
+            .retry(Duration::from_secs(3))
+            .data("first\nsecond"),
+        SseEvent::new().json(&[1, 2]).unwrap(),
+    ]);
+    let response = Sse::new(events).into_response().unwrap();
+    assert_eq!(response.content_type, Some(ContentType::EVENT_STREAM));
+    assert_eq!(response.headers.get("cache-control"), Some("no-cache"));
+
+    let chunks: Vec<_> = response.content.into_stream().collect().await;
+    let body: Vec<u8> = chunks.into_iter().flat_map(Result::unwrap).collect();
+    assert_eq!(
+        String::from_utf8(body).unwrap(),
+        "event:tick\nid:1\nretry:3000\ndata:first\ndata:second\n\ndata:[1,2]\n\n"
+    );
+
+    // a newline would inject another field.
+    let event = SseEvent::new().event("tick\ndata:injected").encode();
+    assert_eq!(event, "event:tick data:injected\n\n");
+}
+
+#[tokio::test]
+async fn test_sse_keep_alive() {
+    let keep_alive = KeepAlive::new()
+        .interval(Duration::from_millis(10))
+        .text("ping");
+    let mut events = Sse::new(futures::stream::pending())
+        .keep_alive(keep_alive)
+        .into_events();
+    let event = events.next().await.unwrap();
+    assert_eq!(event.encode(), ":ping\n\n");
+}
+
+struct MyEchoSocket;
+
+#[derive(Hash)]
+struct EchoRoute<'a>(&'a str);
+
+impl<'url> Route<'url> for EchoRoute<'url> {
+    fn try_from_url(url: UrlInfos<'_, 'url>) -> Option<Self> {
+        match url.segments() {
+            ["echo", room] => Some(EchoRoute(room)),
+            _ => None,
+        }
+    }
+}
+
+impl Routable for MyEchoSocket {
+    type Route<'a> = EchoRoute<'a>;
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct EchoMessage {
+    text: String,
+}
+
+#[async_trait]
+impl WebSocketRoute for MyEchoSocket {
+    type State<'r> = State<&'r AtomicUsize>;
+    type Incoming = EchoMessage;
+    type Outgoing = EchoMessage;
+
+    async fn on_connect<'url, 'r>(
+        route: EchoRoute<'url>,
+        connections: State<&'r AtomicUsize>,
+        mut channel: WsChannel<EchoMessage, EchoMessage>,
+    ) {
+        let count = connections.fetch_add(1, Ordering::Relaxed) + 1;
+        while let Some(Ok(message)) = channel.recv().await {
+            let text = format!("{} #{}: {}", route.0, count, message.text);
+            if channel.send(&EchoMessage { text }).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_websocket_route() {
+    let app = App::new()
+        .websocket(MyEchoSocket)
+        .middleware_for(RouteGroup::api(), RequireUser)
+        .state_unwrap(AtomicUsize::new(0));
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/ws/echo/lobby");
+    assert!(server.is_websocket(&url_infos));
+    assert!(!server.is_websocket(&OwnedUrlInfos::parse_from_url("/ws/unknown")));
+    assert!(!server.is_websocket(&OwnedUrlInfos::parse_from_url("/echo/lobby")));
+    let unknown_url = OwnedUrlInfos::parse_from_url("/ws/unknown");
+    let result = server
+        .accept_websocket(None, &unknown_url, &Headers::new())
+        .await;
+    assert!(result.is_none());
+
+    // the middlewares run before the upgrade.
+    let result = server
+        .accept_websocket(None, &url_infos, &Headers::new())
+        .await;
+    match result {
+        Some(Ok(WebSocketAccept::Respond(response))) => {
+            assert_eq!(response.into_response().status, Status::UNAUTHORIZED);
+        }
+        _ => panic!("expected the middleware to reject the upgrade"),
+    }
+
+    let headers = Headers::from_iter([("Authorization", "user-bob")]);
+    let upgrade = match server.accept_websocket(None, &url_infos, &headers).await {
+        Some(Ok(WebSocketAccept::Upgrade(upgrade))) => upgrade,
+        _ => panic!("expected the upgrade to be accepted"),
+    };
+    assert_eq!(upgrade.headers().get("X-User"), Some("bob"));
+    let connect = upgrade.connect().unwrap();
+
+    let incoming = futures::stream::iter([
+        Ok(WsMessage::Text(r#"{"text":"hello"}"#.into())),
+        Ok(WsMessage::Binary(vec![1, 2])),
+        Ok(WsMessage::Text(r#"{"text":"ignored"}"#.into())),
+    ]);
+    let (sender, receiver) = futures::channel::mpsc::unbounded();
+    let outgoing = sender.sink_map_err(|_| WsError::Closed);
+    connect(WebSocket::new(incoming, outgoing)).await;
+
+    // the binary message is unexpected for the JSON codec and ends the echo loop.
+    let sent: Vec<WsMessage> = receiver.collect().await;
+    assert_eq!(
+        sent,
+        [WsMessage::Text(r#"{"text":"lobby #1: hello"}"#.into())]
+    );
+}
+
+#[tokio::test]
+async fn test_websocket_missing_state() {
+    let server = App::new().websocket(MyEchoSocket).into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/ws/echo/lobby");
+    let upgrade = match server
+        .accept_websocket(None, &url_infos, &Headers::new())
+        .await
+    {
+        Some(Ok(WebSocketAccept::Upgrade(upgrade))) => upgrade,
+        _ => panic!("expected the upgrade to be accepted"),
+    };
+    // the handshake fails before the connection is upgraded.
+    let err = upgrade.connect().err().unwrap();
+    assert!(matches!(err, StonkksError::MissingState(_)));
+    let response = server.error_response(&url_infos, &Headers::new(), &err);
+    assert_eq!(response.status, 500);
+    assert!(matches!(response.body, ErrorBody::Json(_)));
+}
+
+struct MyWhoAmIApi;
+
+test_route!(MyWhoAmIApi at "account/whoami");
+
+#[async_trait]
+impl Api for MyWhoAmIApi {
+    type Err<'url> = RequestRejection;
+    type State<'r> = Headers;
+    type Body<'r> = ();
+    type Output<'url> = Json<String>;
+
+    async fn respond<'url, 'r>(
+        _method: Method,
+        _route: PathRoute<Self>,
+        headers: Headers,
+        _body: (),
+    ) -> Result<Json<String>, RequestRejection> {
+        headers.require("X-User").map(|user| Json(user.to_owned()))
+    }
+}
+
+struct RequireUser;
+
+#[async_trait]
+impl Middleware for RequireUser {
+    async fn handle<'a, 'url>(
+        &self,
+        request: &mut MiddlewareRequest<'a, 'url>,
+        next: Next<'_>,
+    ) -> Option<Result<ServerResponse, StonkksError>> {
+        let user = match request.headers().get("Authorization") {
+            Some(token) => token.trim_start_matches("user-").to_owned(),
+            None => {
+                let response = Response::empty(Status::UNAUTHORIZED);
+                return Some(Ok(ServerResponse::Api(response)));
+            }
+        };
+        request.headers_mut().append("X-User", user);
+        next.run(request).await
+    }
+}
+
+struct PoweredBy;
+
+#[async_trait]
+impl Middleware for PoweredBy {
+    async fn handle<'a, 'url>(
+        &self,
+        request: &mut MiddlewareRequest<'a, 'url>,
+        next: Next<'_>,
+    ) -> Option<Result<ServerResponse, StonkksError>> {
+        let State(counter) = match request.extract::<State<&AtomicUsize>>() {
+            Ok(counter) => counter,
+            Err(err) => return Some(Err(err)),
+        };
+        let count = counter.fetch_add(1, Ordering::Relaxed) + 1;
+        let response = next.run(request).await?;
+        Some(response.map(|response| {
+            response
+                .with_header("X-Powered-By", "stonkks")
+                .with_header("X-Request-Count", count.to_string())
+        }))
+    }
+}
+
+fn expect_api_response(result: Option<Result<ServerResponse, StonkksError>>) -> Response {
+    match result {
+        Some(Ok(ServerResponse::Api(response))) => response,
+        _ => panic!("expected an api response"),
+    }
+}
+
+#[tokio::test]
+async fn test_middlewares() {
+    let app = App::new()
+        .api(MyWhoAmIApi)
+        .api(MyItemsApi)
+        .middleware(PoweredBy)
+        .middleware_for(RouteGroup::api().prefix("/account"), RequireUser)
+        .state_unwrap(AtomicUsize::new(0));
+    let server = app.into_server();
+
+    let url_infos = OwnedUrlInfos::parse_from_url("/api/account/whoami");
+    let headers: Headers = [("Authorization", "user-alice")].into_iter().collect();
+    let response = expect_api_response(
+        server
+            .respond(Method::Get, &url_infos, &headers, &RequestBody::empty())
+            .await,
+    );
+    assert_eq!(response.content.as_bytes().unwrap(), b"\"alice\"");
+    assert_eq!(response.headers.get("x-powered-by"), Some("stonkks"));
+
+    // short-circuited by `RequireUser`, the response still goes through `PoweredBy`.
+    let response = expect_api_response(
+        server
+            .respond(
+                Method::Get,
+                &url_infos,
+                &Headers::new(),
+                &RequestBody::empty(),
+            )
+            .await,
+    );
+    assert_eq!(response.status, Status::UNAUTHORIZED);
+    assert_eq!(response.headers.get("x-powered-by"), Some("stonkks"));
+    assert_eq!(response.headers.get("x-request-count"), Some("2"));
+
+    // outside of the group of `RequireUser`.
+    let url_infos = OwnedUrlInfos::parse_from_url("/api/items");
+    let response = expect_api_response(
+        server
+            .respond(
+                Method::Get,
+                &url_infos,
+                &Headers::new(),
+                &RequestBody::empty(),
+            )
+            .await,
+    );
+    assert_eq!(response.content.as_bytes().unwrap(), b"\"listed\"");
+}
+
+#[tokio::test]
+async fn test_cors() {
+    let cors = Cors::new()
+        .allow_origin("https://partner.example.com")
+        .allow_headers(["Content-Type"])
+        .expose_headers(["X-Total-Count"])
+        .allow_credentials(true)
+        .max_age(Duration::from_secs(600));
+    let app = App::new().api(MyItemsApi).cors(cors);
+    let server = app.into_server();
+    let url_infos = OwnedUrlInfos::parse_from_url("/api/items");
+
+    let preflight: Headers = [
+        ("Origin", "https://partner.example.com"),
+        ("Access-Control-Request-Method", "POST"),
+        ("Access-Control-Request-Headers", "content-type"),
+    ]
+    .into_iter()
+    .collect();
+    let response = expect_api_response(
+        server
+            .respond(
+                Method::Options,
+                &url_infos,
+                &preflight,
+                &RequestBody::empty(),
+            )
+            .await,
+    );
+    assert_eq!(response.status, Status::NO_CONTENT);
+    let cors_header = |name| response.headers.get(name);
+    assert_eq!(
+        cors_header("Access-Control-Allow-Origin"),
+        Some("https://partner.example.com")
+    );
+    assert_eq!(
+        cors_header("Access-Control-Allow-Methods"),
+        Some("GET, POST, PUT, PATCH, DELETE")
+    );
+    assert_eq!(
+        cors_header("Access-Control-Allow-Headers"),
+        Some("content-type")
+    );
+    assert_eq!(
+        cors_header("Access-Control-Allow-Credentials"),
+        Some("true")
+    );
+    assert_eq!(cors_header("Access-Control-Max-Age"), Some("600"));
+
+    let other_origin: Headers = [
+        ("Origin", "https://evil.example.com"),
+        ("Access-Control-Request-Method", "POST"),
+    ]
+    .into_iter()
+    .collect();
+    let response = expect_api_response(
+        server
+            .respond(
+                Method::Options,
+                &url_infos,
+                &other_origin,
+                &RequestBody::empty(),
+            )
+            .await,
+    );
+    assert_eq!(response.status, Status::FORBIDDEN);
+
+    let headers: Headers = [("Origin", "https://partner.example.com")]
+        .into_iter()
+        .collect();
+    let response = expect_api_response(
+        server
+            .respond(Method::Post, &url_infos, &headers, &RequestBody::empty())
+            .await,
+    );
+    assert_eq!(response.content.as_bytes().unwrap(), b"\"created\"");
+    assert_eq!(
+        response.headers.get("Access-Control-Allow-Origin"),
+        Some("https://partner.example.com")
+    );
+    assert_eq!(response.headers.get("Vary"), Some("Origin"));
+    assert_eq!(
+        response.headers.get("Access-Control-Expose-Headers"),
+        Some("X-Total-Count")
+    );
+
+    // errors of api routes get the CORS headers too.
+    let error = match server
+        .respond(Method::Delete, &url_infos, &headers, &RequestBody::empty())
+        .await
+    {
+        Some(Err(error)) => error,
+        _ => panic!("expected a method not allowed error"),
+    };
+    let ErrorResponse {
+        status,
+        headers: error_headers,
+        ..
+    } = server.error_response(&url_infos, &headers, &error);
+    assert_eq!(status, 405);
+    assert!(error_headers.contains(&(
+        "Access-Control-Allow-Origin",
+        "https://partner.example.com".to_owned()
+    )));
+
+    // requests without an origin are not cross-origin.
+    let response = expect_api_response(
+        server
+            .respond(
+                Method::Get,
+                &url_infos,
+                &Headers::new(),
+                &RequestBody::empty(),
+            )
+            .await,
+    );
+    assert_eq!(response.headers.get("Access-Control-Allow-Origin"), None);
+}
+
+#[tokio::test]
+async fn test_rate_limit() {
+    let app = App::new().api(MyItemsApi).middleware_for(
+        RouteGroup::api().prefix("/items"),
+        RateLimit::per_minute(2).key_by_header("X-Api-Key"),
+    );
+    let server = app.into_server();
+    let url_infos = OwnedUrlInfos::parse_from_url("/api/items");
+    let first_key: Headers = [("X-Api-Key", "first")].into_iter().collect();
+    let second_key: Headers = [("X-Api-Key", "second")].into_iter().collect();
+
+    for _ in 0..2 {
+        let response = expect_api_response(
+            server
+                .respond(Method::Get, &url_infos, &first_key, &RequestBody::empty())
+                .await,
+        );
+        assert_eq!(response.content.as_bytes().unwrap(), b"\"listed\"");
+    }
+    let error = match server
+        .respond(Method::Get, &url_infos, &first_key, &RequestBody::empty())
+        .await
+    {
+        Some(Err(error)) => error,
+        _ => panic!("expected the request to be rate limited"),
+    };
+    assert_eq!(error.status(), 429);
+    let ErrorResponse {
+        status, headers, ..
+    } = server.error_response(&url_infos, &first_key, &error);
+    assert_eq!(status, 429);
+    let retry_after = headers
+        .iter()
+        .find(|(name, _)| *name == "Retry-After")
+        .map(|(_, value)| value.parse::<u64>().unwrap());
+    assert_eq!(retry_after, Some(30));
+
+    // each key has its own bucket.
+    let response = expect_api_response(
+        server
+            .respond(Method::Get, &url_infos, &second_key, &RequestBody::empty())
+            .await,
+    );
+    assert_eq!(response.content.as_bytes().unwrap(), b"\"listed\"");
+
+    // keyed by the ip given by the adapter by default.
+    let app = App::new()
+        .api(MyItemsApi)
+        .middleware(RateLimit::per_minute(1));
+    let server = app.into_server();
+    let first_ip = Some(IpAddr::from([127, 0, 0, 1]));
+    let second_ip = Some(IpAddr::from([127, 0, 0, 2]));
+    let mut statuses = Vec::new();
+    for client_ip in [first_ip, first_ip, second_ip] {
+        let result = server
+            .respond_from(
+                client_ip,
+                Method::Get,
+                &url_infos,
+                &Headers::new(),
+                &RequestBody::empty(),
+            )
+            .await;
+        statuses.push(match result {
+            Some(Ok(_)) => 200,
+            Some(Err(error)) => error.status(),
+            None => panic!("expected a response"),
+        });
+    }
+    assert_eq!(statuses, [200, 429, 200]);
+
+    // requests without a key are not limited, they don't share a bucket.
+    for _ in 0..2 {
+        let response = expect_api_response(
+            server
+                .respond(
+                    Method::Get,
+                    &url_infos,
+                    &Headers::new(),
+                    &RequestBody::empty(),
+                )
+                .await,
+        );
+        assert_eq!(response.content.as_bytes().unwrap(), b"\"listed\"");
+    }
+}